@@ -0,0 +1,177 @@
+use crate::{Expr, Operator, RationalSearch};
+use num::{BigInt, BigRational, One, Signed, ToPrimitive, Zero, rational::Rational64};
+use fxhash::FxHashMap as HashMap;
+
+const MAX_CONVERGENTS: usize = 64;
+
+/// The closest expression to a real target found by [`RationalSearch::approximate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Approximation {
+    pub expr: Expr,
+    pub value: Rational64,
+    pub copies: usize,
+    pub error: f64,
+}
+
+/// Parses a decimal literal such as `-3.14159265358979323846` without losing precision.
+pub fn parse_decimal(s: &str) -> Option<BigRational> {
+    let s = s.trim();
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+
+    let (int_part, frac_part) = match s.find('.') {
+        Some(idx) => (&s[..idx], &s[idx + 1..]),
+        None => (s, ""),
+    };
+    if (int_part.is_empty() && frac_part.is_empty()) ||
+            !int_part.chars().chain(frac_part.chars()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let numer = BigInt::parse_bytes(format!("{}{}", int_part, frac_part).as_bytes(), 10)?;
+    let denom = num::pow(BigInt::from(10), frac_part.len());
+    let num = BigRational::new(numer, denom);
+
+    Some(if neg { -num } else { num })
+}
+
+impl RationalSearch {
+    /// Searches for the expression closest to `target` which uses at most `max_copies` copies.
+    ///
+    /// Besides the values in the search tables, the continued-fraction convergents of `target`
+    /// are tried as `(p/q)` where both `p` and `q` are generatable.
+    pub fn approximate(&self, target: &BigRational, max_copies: usize) -> Option<Approximation> {
+        let mut best: Option<(BigRational, usize, Rational64, Expr)> = None;
+        let mut update = |value: Rational64, copies: usize, expr: &dyn Fn() -> Expr| {
+            let error = (BigRational::new((*value.numer()).into(), (*value.denom()).into()) - target).abs();
+            let is_better = match &best {
+                Some((best_error, best_copies, _, _)) => (&error, copies) < (best_error, *best_copies),
+                None => true,
+            };
+
+            if is_better {
+                best = Some((error, copies, value, expr()));
+            }
+        };
+
+        let mut int_copies = HashMap::default();
+        for (i, nums) in self.generatable_nums.iter().enumerate().take(max_copies) {
+            for num in nums {
+                update(*num, i + 1, &|| self.known_expr(num));
+                if num.is_integer() {
+                    int_copies.insert(*num, i + 1);
+                }
+            }
+        }
+
+        for convergent in convergents(target) {
+            let numer = Rational64::from_integer(*convergent.numer());
+            let denom = Rational64::from_integer(*convergent.denom());
+            if let (Some(ncopies), Some(dcopies)) = (int_copies.get(&numer), int_copies.get(&denom)) {
+                if ncopies + dcopies <= max_copies {
+                    update(convergent, ncopies + dcopies, &|| {
+                        Expr::binop(
+                            Operator::Div,
+                            self.known_expr(&numer),
+                            self.known_expr(&denom),
+                            true)
+                    });
+                }
+            }
+        }
+
+        best.map(|(error, copies, value, expr)| Approximation {
+            expr,
+            value,
+            copies,
+            error: error.to_f64().unwrap_or(f64::INFINITY),
+        })
+    }
+
+    pub fn approximate_f64(&self, target: f64, max_copies: usize) -> Option<Approximation> {
+        self.approximate(&BigRational::from_float(target)?, max_copies)
+    }
+}
+
+// continued-fraction convergents of `target` which fit in Rational64
+fn convergents(target: &BigRational) -> Vec<Rational64> {
+    let mut ret = Vec::new();
+    let (mut h0, mut h1) = (BigInt::zero(), BigInt::one());
+    let (mut k0, mut k1) = (BigInt::one(), BigInt::zero());
+    let mut x = target.clone();
+
+    for _ in 0..MAX_CONVERGENTS {
+        let a = x.floor().to_integer();
+        let h2 = &a * &h1 + &h0;
+        let k2 = &a * &k1 + &k0;
+        match (h2.to_i64(), k2.to_i64()) {
+            (Some(h), Some(k)) => ret.push(Rational64::new(h, k)),
+            _ => break,
+        }
+
+        let frac = x - BigRational::from_integer(a);
+        if frac.is_zero() {
+            break;
+        }
+        x = frac.recip();
+        h0 = std::mem::replace(&mut h1, h2);
+        k0 = std::mem::replace(&mut k1, k2);
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_decimal() {
+        assert_eq!(parse_decimal("3.14"), Some(BigRational::new(314.into(), 100.into())));
+        assert_eq!(parse_decimal("-0.5"), Some(BigRational::new((-1).into(), 2.into())));
+        assert_eq!(parse_decimal("42"), Some(BigRational::from_integer(42.into())));
+        assert_eq!(parse_decimal("."), None);
+        assert_eq!(parse_decimal("1e3"), None);
+    }
+
+    #[test]
+    fn test_convergents() {
+        let pi = parse_decimal("3.14159265358979323846").unwrap();
+
+        assert_eq!(convergents(&pi)[..4], [
+            3.into(),
+            Rational64::new(22, 7),
+            Rational64::new(333, 106),
+            Rational64::new(355, 113),
+        ]);
+    }
+
+    #[test]
+    fn approximate() {
+        let search = RationalSearch::new(&crate::nananiji_lists(), 2, 10);
+        let pi = parse_decimal("3.14159265358979323846").unwrap();
+
+        let approx = search.approximate(&pi, 1).unwrap();
+        assert_eq!(approx.expr.to_string(), "(22/7)");
+        assert_eq!(approx.value, Rational64::new(22, 7));
+        assert_eq!(approx.copies, 1);
+        assert!((approx.error - 1.2644892673496777e-3).abs() < 1e-12);
+
+        let approx = search.approximate_f64(std::f64::consts::PI, 2).unwrap();
+        assert_eq!(approx.value, Rational64::new(22, 7));
+        assert_eq!(approx.copies, 1);
+        assert!(search.approximate(&pi, 0).is_none());
+    }
+
+    #[test]
+    fn approximate_convergent() {
+        let search = RationalSearch::new(&[vec![355], vec![113]], 1, 10);
+        let pi = parse_decimal("3.14159265358979323846").unwrap();
+
+        let approx = search.approximate(&pi, 2).unwrap();
+        assert_eq!(approx.expr.to_string(), "(355/113)");
+        assert_eq!(approx.copies, 2);
+    }
+}
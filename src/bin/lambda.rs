@@ -1,9 +1,16 @@
 use lambda_runtime::{error::HandlerError, lambda, Context};
-use nananiji_calculator::ExpressionGenerator;
+use nananiji_calculator::{
+    DenomCut, ExpressionGenerator, JsonExpr, SearchLimits, SearchParams, SeedOrder, default_cache_dir, hanshin_lists, kyojin_lists,
+    nananiji_lists,
+};
 use serde::{Serialize, Deserialize};
-use std::{fs::File, path::Path};
-use std::io::Read;
-use anyhow::Result;
+use std::{env, fs::File};
+use std::io::BufReader;
+use anyhow::{Result, anyhow};
+
+// the environment variables of the parameters of the pre-calculation files
+const SEARCH_DEPTH_ENV: &str = "NANANIJI_SEARCH_DEPTH";
+const DENOM_CUT_ENV: &str = "NANANIJI_DENOM_CUT";
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
 #[serde(tag = "name", content = "split", rename_all="lowercase")]
@@ -13,16 +20,27 @@ enum ListName {
     Kyojin(bool),
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all="lowercase")]
+enum ResponseFormat {
+    Text,
+    Ast,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct Request {
     value: String,
     list_name: ListName,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    format: Option<ResponseFormat>,
 }
 
 #[derive(Serialize, Clone)]
 struct RequestResult {
     req: Request,
     expr: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ast: Option<JsonExpr>,
 }
 
 struct PreloadGenerators {
@@ -46,12 +64,31 @@ impl PreloadGenerators {
 }
 
 fn main() -> Result<()> {
+    let search_depth = match env::var(SEARCH_DEPTH_ENV) {
+        Ok(depth) => depth.parse()?,
+        Err(_) => 3,
+    };
+    let denom_cut: DenomCut = match env::var(DENOM_CUT_ENV) {
+        Ok(denom_cut) => denom_cut.parse()?,
+        Err(_) => 10.into(),
+    };
+    let load = |prefix: &str, num_lists: Vec<Vec<i64>>| {
+        let params = SearchParams {
+            num_lists,
+            seed_order: SeedOrder::Strict,
+            search_depth,
+            denom_cut: denom_cut.clone(),
+            limits: SearchLimits::default(),
+        };
+        load_generator(prefix, &params)
+    };
+
     let gens = PreloadGenerators {
-        nananiji: load_generator(Path::new("nananiji.bin"))?,
-        hanshin: load_generator(Path::new("hanshin.bin"))?,
-        hanshin_a: load_generator(Path::new("hanshin_a.bin"))?,
-        kyojin: load_generator(Path::new("kyojin.bin"))?,
-        kyojin_a: load_generator(Path::new("kyojin_a.bin"))?,
+        nananiji: load("nananiji", nananiji_lists())?,
+        hanshin: load("hanshin", hanshin_lists(false))?,
+        hanshin_a: load("hanshin_a", hanshin_lists(true))?,
+        kyojin: load("kyojin", kyojin_lists(false))?,
+        kyojin_a: load("kyojin_a", kyojin_lists(true))?,
     };
 
     lambda!(move |req: Request, _ctx: Context| handler(req, &gens));
@@ -65,19 +102,31 @@ fn handler(req: Request, gens: &PreloadGenerators) -> Result<RequestResult, Hand
     let generator = gens.choose_generator(req.list_name);
     let value = req.value.parse::<i64>()
         .map_err(|_| HandlerError::from("value parse failed"))?;
-    let expr = generator.generate(value);
+    let (expr, copies) = generator.generate_with_count(value)
+        .map_err(|err| HandlerError::from(err.as_str()))?;
+    let ast = if req.format == Some(ResponseFormat::Ast) {
+        Some(JsonExpr::new(&expr, Some(copies)))
+    } else {
+        None
+    };
 
     Ok(RequestResult {
         req,
-        expr
+        expr: expr.to_string(),
+        ast,
     })
 }
 
-fn load_generator(filepath: &Path) -> Result<ExpressionGenerator> {
-    let mut file = File::open(filepath)?;
-    let mut u8_encoded = Vec::new();
-    file.read_to_end(&mut u8_encoded)?;
-    let generator: ExpressionGenerator = bincode::deserialize(&u8_encoded)?;
+// loads the file saved by `nananiji-calculator --write-file` with the same parameters from the cache
+// directory, or from the current directory if it is unknown
+fn load_generator(prefix: &str, params: &SearchParams) -> Result<ExpressionGenerator> {
+    let filepath = default_cache_dir().unwrap_or_default().join(params.file_name(prefix));
+    let file = File::open(&filepath).map_err(|err| anyhow!("cannot open {}: {}", filepath.display(), err))?;
+    let file = BufReader::new(file);
+    let generator = ExpressionGenerator::load(file).map_err(|err| anyhow!("{}: {}", filepath.display(), err))?;
+    generator.params()
+        .check(params)
+        .map_err(|err| anyhow!("{}: {}", filepath.display(), err))?;
 
     Ok(generator)
-}
\ No newline at end of file
+}
@@ -1,8 +1,16 @@
-use nananiji_calculator::ExpressionGenerator;
-use std::{fs::File, path::Path};
-use std::io::{Write, Read};
-use anyhow::Result;
-use clap::{App, Arg, arg_enum, crate_authors, crate_description, crate_name, crate_version, value_t};
+use nananiji_calculator::{
+    CodeLanguage, CountdownSearch, CountdownSolution, DenomCut, Expr, ExpressionGenerator, JsonExpr, Limit,
+    Operator, RationalSearch, SearchLimits, SearchParams, SeedOrder, SvgLayout, SvgOptions, TraceFormat,
+    UnicodeOptions, default_cache_dir, exact_once_exprs, exact_once_target, hanshin_lists, kyojin_lists,
+    nananiji_lists, parse_decimal, render_trace, to_code, to_dot, to_japanese_reading, to_latex, to_mathml_equation,
+    to_rpn, to_sexpr, to_svg, to_unicode, validate_expr,
+};
+use num::rational::Rational64;
+use serde::Serialize;
+use std::{fs::File, ops::RangeInclusive, path::{Path, PathBuf}};
+use std::io::{self, BufRead, BufReader, Write};
+use anyhow::{Result, anyhow, bail};
+use clap::{App, Arg, ArgMatches, arg_enum, crate_authors, crate_description, crate_name, crate_version, value_t};
 
 arg_enum! {
     #[derive(PartialEq, Debug, Clone, Copy)]
@@ -13,11 +21,64 @@ arg_enum! {
     }
 }
 
+arg_enum! {
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum SeedOrderName {
+        Strict,
+        WithinGroup,
+        AcrossExpression,
+    }
+}
 
-fn main() -> Result<()> {
-    env_logger::init();
+arg_enum! {
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum TraceFormatName {
+        Text,
+        Markdown,
+    }
+}
+
+arg_enum! {
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum FormatName {
+        Text,
+        Unicode,
+        Reading,
+        Latex,
+        MathMl,
+        Json,
+        Rpn,
+        Sexpr,
+        Dot,
+        Svg,
+        SvgTree,
+        Rust,
+        Python,
+        Js,
+    }
+}
+
+arg_enum! {
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum BatchFormatName {
+        Csv,
+        Tsv,
+        Jsonl,
+    }
+}
 
-    let matches = App::new(crate_name!())
+impl From<SeedOrderName> for SeedOrder {
+    fn from(name: SeedOrderName) -> Self {
+        match name {
+            SeedOrderName::Strict => SeedOrder::Strict,
+            SeedOrderName::WithinGroup => SeedOrder::WithinGroup,
+            SeedOrderName::AcrossExpression => SeedOrder::AcrossExpression,
+        }
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    App::new(crate_name!())
         .version(crate_version!())
         .author(crate_authors!())
         .about(crate_description!())
@@ -31,15 +92,27 @@ fn main() -> Result<()> {
             .case_insensitive(true)
             .default_value("Nananiji"))
         .arg(Arg::with_name("out_file")
-            .help("Path of the directory for saving pre-calculation file")
+            .help("Save the pre-calculation file to the cache directory")
             .short("w")
             .long("write-file")
             .takes_value(false))
         .arg(Arg::with_name("in_file")
-            .help("Path of the directory for loading pre-calculation file")
+            .help("Load the pre-calculation file from the cache directory")
             .short("r")
             .long("read-file")
             .takes_value(false))
+        .arg(Arg::with_name("cache_dir")
+            .help("The directory of pre-calculation files \
+                [default: $NANANIJI_CACHE_DIR, $XDG_CACHE_HOME/nananiji-calculator or ~/.cache/nananiji-calculator]")
+            .long("cache-dir")
+            .value_name("DIR")
+            .takes_value(true))
+        .arg(Arg::with_name("cache_file")
+            .help("The pre-calculation file, instead of the one named after the options in the cache directory")
+            .long("cache-file")
+            .value_name("FILE")
+            .takes_value(true)
+            .conflicts_with("cache_dir"))
         .arg(Arg::with_name("search_depth")
             .help("The depth of search")
             .short("d")
@@ -48,46 +121,238 @@ fn main() -> Result<()> {
             .default_value("3")
             .takes_value(true))
         .arg(Arg::with_name("denom_cut")
-            .help("The depth of search")
+            .help("The cut of denominators, or a schedule from depth 2 such as 30,10,5")
             .short("c")
             .long("denom-cut")
             .value_name("MAX_DENOM")
             .default_value("10")
             .takes_value(true))
+        .arg(Arg::with_name("max_entries")
+            .help("The maximum number of new values at each depth of search")
+            .long("max-entries")
+            .value_name("ENTRIES")
+            .takes_value(true))
+        .arg(Arg::with_name("max_numer")
+            .help("Discard values whose numerator exceeds MAX_NUMER in absolute value")
+            .long("max-numer")
+            .value_name("MAX_NUMER")
+            .takes_value(true))
+        .arg(Arg::with_name("memory_budget")
+            .help("The memory budget of the search tables in MiB (estimated)")
+            .long("memory-budget")
+            .value_name("MIB")
+            .takes_value(true))
+        .arg(Arg::with_name("search_file")
+            .help("Resume the search saved in FILE if it exists, and save it after each depth as a checkpoint")
+            .long("search-file")
+            .value_name("FILE")
+            .takes_value(true))
+        .arg(Arg::with_name("stats")
+            .help("Print the statistics of the search, and the path of the file saved by -w, to stderr")
+            .long("stats")
+            .takes_value(false))
         .arg(Arg::with_name("allow_split")
             .help("Allow spliting like 3-34. This option is available for hanshin and kyojin (not nananiji)")
             .short("a")
             .long("allow-split")
             .value_name("ALLOW_SPLIT")
             .takes_value(false))
+        .arg(Arg::with_name("exact_once")
+            .help("Use each of at most 3 numbers exactly once (e.g. 2,2,7) and list the reachable values")
+            .short("e")
+            .long("exact-once")
+            .value_name("NUMS")
+            .takes_value(true)
+            .use_delimiter(true))
+        .arg(Arg::with_name("countdown")
+            .help("Use each of the given numbers at most once in any order (e.g. 7,2,2) to reach TARGET_NUM")
+            .short("C")
+            .long("countdown")
+            .value_name("NUMS")
+            .takes_value(true)
+            .use_delimiter(true)
+            .conflicts_with("exact_once")
+            .requires("target_num"))
+        .arg(Arg::with_name("approximate")
+            .help("Approximate a real number (decimal, or one of pi, e, sqrt2) within DEPTH copies")
+            .short("x")
+            .long("approximate")
+            .value_name("REAL")
+            .takes_value(true)
+            .allow_hyphen_values(true))
+        .arg(Arg::with_name("seed_order")
+            .help("Whether the numbers of each split may be reordered (e.g. 7,2,2 instead of 2,2,7)")
+            .short("o")
+            .long("seed-order")
+            .takes_value(true)
+            .value_name("SEED_ORDER")
+            .possible_values(&SeedOrderName::variants())
+            .case_insensitive(true)
+            .default_value("Strict"))
+        .arg(Arg::with_name("copies")
+            .help("Use exactly COPIES copies of the number set (at most 1024)")
+            .short("k")
+            .long("copies")
+            .value_name("COPIES")
+            .takes_value(true))
+        .arg(Arg::with_name("explain")
+            .help("Print the decomposition steps taken to generate the expression")
+            .long("explain")
+            .takes_value(false))
+        .arg(Arg::with_name("trace")
+            .help("Print every intermediate result of evaluating the expression")
+            .short("t")
+            .long("trace")
+            .takes_value(true)
+            .value_name("TRACE_FORMAT")
+            .possible_values(&TraceFormatName::variants())
+            .case_insensitive(true))
+        .arg(Arg::with_name("format")
+            .help("The output format of expressions")
+            .short("f")
+            .long("format")
+            .takes_value(true)
+            .value_name("FORMAT")
+            .possible_values(&FormatName::variants())
+            .case_insensitive(true)
+            .default_value("Text"))
+        .arg(Arg::with_name("full_width")
+            .help("Use full-width digits and parentheses in the Unicode format")
+            .long("full-width")
+            .takes_value(false))
+        .arg(Arg::with_name("svg_width")
+            .help("The width of SVG images in pixels")
+            .long("svg-width")
+            .value_name("WIDTH")
+            .default_value("1200")
+            .takes_value(true))
+        .arg(Arg::with_name("font")
+            .help("The font family of SVG images")
+            .long("font")
+            .value_name("FONT")
+            .default_value("serif")
+            .takes_value(true))
+        .arg(Arg::with_name("range")
+            .help("Generate every number in the range (e.g. 1..10000, or 1..=10000 to include the end)")
+            .long("range")
+            .value_name("RANGE")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .conflicts_with("target_num"))
+        .arg(Arg::with_name("input")
+            .help("Generate the numbers listed line by line in FILE (- for stdin)")
+            .long("input")
+            .value_name("FILE")
+            .takes_value(true)
+            .conflicts_with_all(&["target_num", "range"]))
+        .arg(Arg::with_name("batch_format")
+            .help("The output format of --range and --input")
+            .long("batch-format")
+            .takes_value(true)
+            .value_name("BATCH_FORMAT")
+            .possible_values(&BatchFormatName::variants())
+            .case_insensitive(true)
+            .default_value("Csv"))
+        .arg(Arg::with_name("threads")
+            .help("The number of threads for --range and --input (all cores by default)")
+            .long("threads")
+            .value_name("THREADS")
+            .takes_value(true))
         .arg(Arg::with_name("target_num")
             .help("The number searched")
             .value_name("TARGET_NUM"))
-        .get_matches();
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let matches = app().get_matches();
+
+    if let Some(nums) = matches.values_of("exact_once") {
+        let nums = nums
+            .map(|num| num.parse::<i64>())
+            .collect::<Result<Vec<_>, _>>()?;
+        return solve_exact_once(&nums, &matches);
+    }
+
+    if let Some(nums) = matches.values_of("countdown") {
+        let nums = nums
+            .map(|num| num.parse::<i64>())
+            .collect::<Result<Vec<_>, _>>()?;
+        return solve_countdown(&nums, &matches);
+    }
 
     let list_name = value_t!(matches, "list_name", ListName)?;
     let allow_split = matches.is_present("allow_split");
+    let seed_order: SeedOrder = value_t!(matches, "seed_order", SeedOrderName)?.into();
+
+    if let Some(target) = matches.value_of("approximate") {
+        return approximate(target, &num_lists(list_name, allow_split), seed_order, &matches);
+    }
 
+    let seed_lists = num_lists(list_name, allow_split);
     let expr_generator: ExpressionGenerator = if matches.is_present("in_file") {
-        let in_filepath = file_path(list_name, allow_split);
-        load_generator(in_filepath)?
+        let expected = SearchParams {
+            num_lists: seed_lists.clone(),
+            seed_order,
+            search_depth: value_t!(matches, "search_depth", usize)?,
+            denom_cut: value_t!(matches, "denom_cut", DenomCut)?,
+            limits: search_limits(&matches)?,
+        };
+        let in_filepath = cache_path(&matches, list_name, allow_split, &expected);
+        let generator = load_generator(&in_filepath)?;
+        generator.params()
+            .check(&expected)
+            .map_err(|err| anyhow!("{}: {}", in_filepath.display(), err))?;
+        generator
     } else {
-        let depth = value_t!(matches, "search_depth", usize)?;
-        let denom_cut = value_t!(matches, "denom_cut", i64)?;
-
-        match &list_name {
-            &ListName::Nananiji => ExpressionGenerator::new_nananiji(depth, denom_cut),
-            &ListName::Hanshin =>  ExpressionGenerator::new_hanshin(allow_split, depth, denom_cut),
-            &ListName::Kyojin => ExpressionGenerator::new_kyojin(allow_split, depth, denom_cut),
-        }
+        let rat_search = build_search(&seed_lists, seed_order, &matches)?;
+        ExpressionGenerator::from_search(&rat_search)
     };
 
     if matches.is_present("out_file") {
-        let out_filepath = file_path(list_name, allow_split);
-        save_generator(out_filepath, &expr_generator)?;
+        let out_filepath = cache_path(&matches, list_name, allow_split, expr_generator.params());
+        save_generator(&out_filepath, &expr_generator)?;
+        if matches.is_present("stats") {
+            eprintln!("saved {}", out_filepath.display());
+        }
+    } else if let Some(range) = matches.value_of("range") {
+        let range = parse_range(range)?;
+        let targets = range.map(|num| Ok(num.to_string()));
+        run_batch(&expr_generator, targets, &matches, &mut io::stdout().lock())?;
+    } else if let Some(in_file) = matches.value_of("input") {
+        let reader: Box<dyn BufRead> = if in_file == "-" {
+            Box::new(BufReader::new(io::stdin()))
+        } else {
+            Box::new(BufReader::new(File::open(in_file)?))
+        };
+        run_batch(&expr_generator, reader.lines(), &matches, &mut io::stdout().lock())?;
     } else if matches.is_present("target_num") {
         let target_num = value_t!(matches, "target_num", i64)?;
-        println!("{} = {}", expr_generator.generate(target_num), target_num);
+        let copies = if matches.is_present("copies") {
+            Some(value_t!(matches, "copies", usize)?)
+        } else {
+            None
+        };
+        let (expr, copies) = generate(&expr_generator, target_num, copies)?;
+        let renderer = Renderer::new(&matches, seed_lists)?;
+        println!("{}", renderer.render(&expr, Some(copies), target_num.into()));
+
+        if matches.is_present("explain") {
+            for step in expr_generator.explain(target_num)? {
+                println!("  {}", step);
+            }
+        }
+
+        if matches.is_present("trace") {
+            let trace_format = match value_t!(matches, "trace", TraceFormatName)? {
+                TraceFormatName::Text => TraceFormat::Text,
+                TraceFormatName::Markdown => TraceFormat::Markdown,
+            };
+            let steps = expr.trace()
+                .ok_or_else(|| anyhow!("{} divides by zero", expr))?;
+            print!("{}", render_trace(&steps, trace_format));
+        }
     } else {
         println!("{}", matches.usage());
     }
@@ -96,29 +361,514 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn file_path(list_name: ListName, allow_split: bool) -> &'static Path {
-    match (list_name, allow_split) {
-        (ListName::Nananiji, _)    => Path::new("nananiji.bin"),
-        (ListName::Hanshin, true)  => Path::new("hanshin_a.bin"),
-        (ListName::Hanshin, false) => Path::new("hanshin.bin"),
-        (ListName::Kyojin, true)   => Path::new("kyojin_a.bin"),
-        (ListName::Kyojin, false)  => Path::new("kyojin.bin"),
+// searches to the given depth, resuming from and checkpointing to `--search-file` if given
+fn build_search(num_lists: &[Vec<i64>], seed_order: SeedOrder, matches: &ArgMatches) -> Result<RationalSearch> {
+    let depth = value_t!(matches, "search_depth", usize)?;
+    let denom_cut = value_t!(matches, "denom_cut", DenomCut)?;
+    let limits = search_limits(matches)?;
+
+    let rat_search = match matches.value_of("search_file") {
+        Some(search_file) => {
+            let search_file = Path::new(search_file);
+            let mut rat_search = if search_file.exists() {
+                let rat_search = load_search(search_file)?;
+                if rat_search.num_lists() != num_lists || rat_search.seed_order() != seed_order {
+                    bail!("{} was searched with different seeds or seed order", search_file.display());
+                }
+                if rat_search.denom_cut() != &denom_cut {
+                    bail!("{} was searched with --denom-cut {}", search_file.display(), rat_search.denom_cut());
+                }
+                if rat_search.limits() != &limits {
+                    bail!("{} was searched with {} instead of {}",
+                        search_file.display(), describe_limits(rat_search.limits()), describe_limits(&limits));
+                }
+                rat_search
+            } else {
+                RationalSearch::new_with_limits(num_lists, seed_order, 1, denom_cut, limits)
+            };
+
+            // a deeper file is used only up to the depth, and is kept as it is
+            rat_search.truncate(depth);
+            for next_depth in rat_search.depth() + 1..=depth {
+                rat_search.extend(next_depth);
+                save_search(search_file, &rat_search)?;
+            }
+            if !search_file.exists() {
+                save_search(search_file, &rat_search)?;
+            }
+            rat_search
+        },
+        None => RationalSearch::new_with_limits(num_lists, seed_order, depth, denom_cut, limits),
+    };
+
+    if matches.is_present("stats") {
+        print_stats(&rat_search);
     }
+    Ok(rat_search)
 }
 
-fn load_generator(filepath: &Path) -> Result<ExpressionGenerator> {
-    let mut file = File::open(filepath)?;
-    let mut u8_encoded = Vec::new();
-    file.read_to_end(&mut u8_encoded)?;
-    let generator: ExpressionGenerator = bincode::deserialize(&u8_encoded)?;
+fn search_limits(matches: &ArgMatches) -> Result<SearchLimits> {
+    let mut limits = SearchLimits::default();
+    if matches.is_present("max_entries") {
+        limits.max_entries_per_depth = Some(value_t!(matches, "max_entries", usize)?);
+    }
+    if matches.is_present("max_numer") {
+        limits.max_numer = Some(value_t!(matches, "max_numer", i64)?);
+    }
+    if matches.is_present("memory_budget") {
+        limits.memory_budget = Some(value_t!(matches, "memory_budget", usize)? << 20);
+    }
 
-    Ok(generator)
+    Ok(limits)
+}
+
+// the options of `limits`, e.g. `--max-entries 5000 --memory-budget 200`
+fn describe_limits(limits: &SearchLimits) -> String {
+    let mut options = Vec::new();
+    if let Some(max_entries) = limits.max_entries_per_depth {
+        options.push(format!("--max-entries {}", max_entries));
+    }
+    if let Some(max_numer) = limits.max_numer {
+        options.push(format!("--max-numer {}", max_numer));
+    }
+    if let Some(memory_budget) = limits.memory_budget {
+        options.push(format!("--memory-budget {}", memory_budget >> 20));
+    }
+
+    if options.is_empty() {
+        "no limits".to_string()
+    } else {
+        options.join(" ")
+    }
+}
+
+fn print_stats(rat_search: &RationalSearch) {
+    for (i, stats) in rat_search.stats().iter().enumerate() {
+        eprint!("depth {}: {} values", i + 1, stats.entries);
+        if stats.pruned_numer > 0 {
+            eprint!(", {} candidates pruned by --max-numer", stats.pruned_numer);
+        }
+        match stats.truncated_by {
+            Some(Limit::Entries) => eprint!(", truncated by --max-entries"),
+            Some(Limit::Memory) => eprint!(", truncated by --memory-budget"),
+            None => {},
+        }
+        eprintln!();
+    }
+    eprintln!("estimated memory: {} KiB", rat_search.estimated_memory() >> 10);
+}
+
+// generates `target_num` with exactly `copies` copies if given
+fn generate(
+    generator: &ExpressionGenerator,
+    target_num: i64,
+    copies: Option<usize>,
+) -> Result<(Expr, usize)> {
+    let (expr, copies) = match copies {
+        Some(copies) => (generator.generate_with_copies(target_num, copies)?, copies),
+        None => generator.generate_with_count(target_num)?,
+    };
+    check_seed_order(generator, &expr)?;
+
+    Ok((expr, copies))
+}
+
+fn check_seed_order(generator: &ExpressionGenerator, expr: &Expr) -> Result<()> {
+    let params = generator.params();
+    if !validate_expr(&expr.to_string(), &params.num_lists, params.seed_order) {
+        bail!("{} violates the seed order {:?}", expr, params.seed_order);
+    }
+
+    Ok(())
+}
+
+fn parse_range(range: &str) -> Result<RangeInclusive<i64>> {
+    let (start, end, inclusive) = if let Some(idx) = range.find("..=") {
+        (&range[..idx], &range[idx + 3..], true)
+    } else if let Some(idx) = range.find("..") {
+        (&range[..idx], &range[idx + 2..], false)
+    } else {
+        bail!("invalid range: {}", range);
+    };
+
+    let start = start.trim().parse::<i64>()?;
+    let end = end.trim().parse::<i64>()?;
+    if inclusive {
+        Ok(start..=end)
+    } else {
+        let end = end.checked_sub(1).ok_or_else(|| anyhow!("invalid range: {}", range))?;
+        Ok(start..=end)
+    }
+}
+
+// the number of lines generated in parallel at a time
+const BATCH_CHUNK: usize = 4096;
+
+#[derive(Serialize)]
+struct BatchRecord<'a> {
+    input: &'a str,
+    expr: Option<String>,
+    copies: Option<usize>,
+    error: Option<String>,
+}
+
+// generates each of `targets` in parallel and writes them in order;
+// errors of each line are written to the output instead of aborting
+fn run_batch(
+    generator: &ExpressionGenerator,
+    targets: impl Iterator<Item=io::Result<String>>,
+    matches: &ArgMatches,
+    out: &mut impl Write,
+) -> Result<()> {
+    let format = value_t!(matches, "batch_format", BatchFormatName)?;
+    let copies = if matches.is_present("copies") {
+        Some(value_t!(matches, "copies", usize)?)
+    } else {
+        None
+    };
+    // the global pool may already be running the search, so the batch gets a pool of its own
+    let mut pool = rayon::ThreadPoolBuilder::new();
+    if matches.is_present("threads") {
+        pool = pool.num_threads(value_t!(matches, "threads", usize)?);
+    }
+    let pool = pool.build()?;
+
+    match format {
+        BatchFormatName::Csv => writeln!(out, "input,expr,copies,error")?,
+        BatchFormatName::Tsv => writeln!(out, "input\texpr\tcopies\terror")?,
+        BatchFormatName::Jsonl => {},
+    }
+
+    let mut lines = targets.peekable();
+    while lines.peek().is_some() {
+        let chunk = lines
+            .by_ref()
+            .take(BATCH_CHUNK)
+            .collect::<io::Result<Vec<_>>>()?;
+        let inputs: Vec<_> = chunk
+            .iter()
+            .map(|line| line.trim())
+            .filter(|input| !input.is_empty())
+            .collect();
+
+        for record in pool.install(|| batch_records(generator, &inputs, copies)) {
+            write_record(out, record, format)?;
+        }
+    }
+
+    Ok(())
+}
+
+// generates the valid numbers of `inputs` with `generate_batch` and pairs the results with the inputs
+fn batch_records<'a>(generator: &ExpressionGenerator, inputs: &[&'a str], copies: Option<usize>) -> Vec<BatchRecord<'a>> {
+    let nums: Vec<_> = inputs.iter().map(|input| input.parse::<i64>()).collect();
+    let valid_nums: Vec<_> = nums.iter().filter_map(|num| num.as_ref().ok().copied()).collect();
+    let mut results = generator.generate_batch(&valid_nums, copies).into_iter();
+
+    inputs
+        .iter()
+        .zip(nums)
+        .map(|(&input, num)| {
+            let result = num
+                .map_err(|err| anyhow!("invalid number {:?}: {}", input, err))
+                .and_then(|_| {
+                    // one result for each valid number
+                    let (expr, copies) = results.next().unwrap()?;
+                    check_seed_order(generator, &expr)?;
+                    Ok((expr, copies))
+                });
+            match result {
+                Ok((expr, copies)) => BatchRecord { input, expr: Some(expr.to_string()), copies: Some(copies), error: None },
+                Err(err) => BatchRecord { input, expr: None, copies: None, error: Some(err.to_string()) },
+            }
+        })
+        .collect()
+}
+
+fn write_record(out: &mut impl Write, record: BatchRecord, format: BatchFormatName) -> Result<()> {
+    match format {
+        BatchFormatName::Jsonl => writeln!(out, "{}", serde_json::to_string(&record)?)?,
+        BatchFormatName::Csv | BatchFormatName::Tsv => {
+            let fields = [
+                record.input.to_string(),
+                record.expr.unwrap_or_default(),
+                record.copies.map(|copies| copies.to_string()).unwrap_or_default(),
+                record.error.unwrap_or_default(),
+            ];
+            let fields: Vec<_> = fields.iter().map(|field| escape_field(field, format)).collect();
+            let sep = if format == BatchFormatName::Csv { "," } else { "\t" };
+            writeln!(out, "{}", fields.join(sep))?;
+        },
+    }
+
+    Ok(())
+}
+
+fn escape_field(field: &str, format: BatchFormatName) -> String {
+    match format {
+        BatchFormatName::Csv if field.contains(&[',', '"', '\n'][..]) => format!("\"{}\"", field.replace('"', "\"\"")),
+        BatchFormatName::Tsv => field.replace(&['\t', '\n'][..], " "),
+        _ => field.to_string(),
+    }
+}
+
+fn solve_exact_once(nums: &[i64], matches: &ArgMatches) -> Result<()> {
+    let renderer = Renderer::new(matches, vec![nums.to_vec()])?;
+    if matches.is_present("target_num") {
+        let target_num = value_t!(matches, "target_num", i64)?;
+        let exprs = exact_once_target(nums, target_num.into())?;
+        if exprs.is_empty() {
+            bail!("{} is unreachable from {:?}", target_num, nums);
+        }
+
+        for expr in exprs {
+            println!("{}", renderer.render(&expr, None, target_num.into()));
+        }
+    } else {
+        for (num, exprs) in exact_once_exprs(nums)? {
+            for expr in exprs {
+                println!("{}", renderer.render(&expr, None, num));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn solve_countdown(nums: &[i64], matches: &ArgMatches) -> Result<()> {
+    let renderer = Renderer::new(matches, vec![nums.to_vec()])?;
+    let target_num = value_t!(matches, "target_num", i64)?;
+    match CountdownSearch::new(nums)?.solve(target_num.into()) {
+        Some(CountdownSolution::Exact(exprs)) => {
+            for expr in exprs {
+                println!("{}", renderer.render(&expr, None, target_num.into()));
+            }
+        },
+        Some(CountdownSolution::Closest(num, exprs)) => {
+            for expr in exprs {
+                let line = renderer.render(&expr, None, num);
+                if renderer.is_document() {
+                    println!("{}", line);
+                } else {
+                    println!("{} (closest to {})", line, target_num);
+                }
+            }
+        },
+        None => bail!("no numbers are given"),
+    }
+
+    Ok(())
+}
+
+fn approximate(target: &str, num_lists: &[Vec<i64>], seed_order: SeedOrder, matches: &ArgMatches) -> Result<()> {
+    let decimal = match target.to_lowercase().as_str() {
+        "pi" => "3.14159265358979323846264338327950288",
+        "e" => "2.71828182845904523536028747135266250",
+        "sqrt2" => "1.41421356237309504880168872420969808",
+        _ => target,
+    };
+    let target_num = match parse_decimal(decimal) {
+        Some(num) => num,
+        None => bail!("invalid real number: {}", target),
+    };
+
+    let depth = value_t!(matches, "search_depth", usize)?;
+    let renderer = Renderer::new(matches, num_lists.to_vec())?;
+    let rat_search = build_search(num_lists, seed_order, matches)?;
+
+    match rat_search.approximate(&target_num, depth) {
+        Some(approx) => {
+            let line = renderer.render(&approx.expr, Some(approx.copies), approx.value);
+            if renderer.is_document() {
+                println!("{}", line);
+            } else {
+                println!("{} (error: {:e})", line, approx.error);
+            }
+        },
+        None => bail!("no expression is available within {} copies", depth),
+    }
+
+    Ok(())
+}
+
+struct Renderer {
+    format: FormatName,
+    num_lists: Vec<Vec<i64>>,
+    svg_options: SvgOptions,
+    unicode_options: UnicodeOptions,
+}
+
+impl Renderer {
+    fn new(matches: &ArgMatches, num_lists: Vec<Vec<i64>>) -> Result<Self> {
+        let format = value_t!(matches, "format", FormatName)?;
+        let svg_options = SvgOptions {
+            layout: if format == FormatName::SvgTree { SvgLayout::Tree } else { SvgLayout::Typeset },
+            width: value_t!(matches, "svg_width", f64)?,
+            font_family: matches.value_of("font").unwrap_or_default().to_string(),
+            ..SvgOptions::default()
+        };
+        let unicode_options = UnicodeOptions {
+            full_width: matches.is_present("full_width"),
+        };
+
+        Ok(Self {
+            format,
+            num_lists,
+            svg_options,
+            unicode_options,
+        })
+    }
+
+    // whether `render` returns a whole document instead of `expr = value`
+    fn is_document(&self) -> bool {
+        !matches!(self.format, FormatName::Text | FormatName::Unicode | FormatName::Reading | FormatName::Latex |
+            FormatName::Rpn | FormatName::Sexpr)
+    }
+
+    // renders `expr = value`, or the whole document for the MathML, JSON, DOT, SVG and code formats
+    fn render(&self, expr: &Expr, copies: Option<usize>, value: Rational64) -> String {
+        let body = match self.format {
+            FormatName::Text => expr.to_string(),
+            FormatName::Unicode => {
+                let equal = if self.unicode_options.full_width { "\u{FF1D}" } else { "=" };
+                return format!("{} {} {}",
+                    to_unicode(expr, &self.unicode_options),
+                    equal,
+                    to_unicode(&value_expr(value), &self.unicode_options));
+            },
+            FormatName::Reading => {
+                return format!("{} は {}", to_japanese_reading(expr), to_japanese_reading(&value_expr(value)));
+            },
+            FormatName::Latex => to_latex(expr),
+            FormatName::MathMl => return to_mathml_equation(expr, value),
+            FormatName::Rpn => to_rpn(expr),
+            FormatName::Sexpr => to_sexpr(expr),
+            FormatName::Json => {
+                return serde_json::to_string(&JsonExpr::new(expr, copies))
+                    .expect("JsonExpr is always serializable");
+            },
+            FormatName::Dot => return to_dot(expr, &self.num_lists).trim_end().to_string(),
+            FormatName::Svg | FormatName::SvgTree => return to_svg(expr, &self.svg_options).trim_end().to_string(),
+            FormatName::Rust => return to_code(expr, value, CodeLanguage::Rust).trim_end().to_string(),
+            FormatName::Python => return to_code(expr, value, CodeLanguage::Python).trim_end().to_string(),
+            FormatName::Js => return to_code(expr, value, CodeLanguage::JavaScript).trim_end().to_string(),
+        };
+
+        format!("{} = {}", body, value)
+    }
+}
+
+// the value as an expression, so that it is rendered in the same style as the expression
+fn value_expr(value: Rational64) -> Expr {
+    if value.is_integer() {
+        Expr::Num(value.to_integer())
+    } else {
+        Expr::binop(Operator::Div, Expr::Num(*value.numer()), Expr::Num(*value.denom()), false)
+    }
+}
+
+fn num_lists(list_name: ListName, allow_split: bool) -> Vec<Vec<i64>> {
+    match list_name {
+        ListName::Nananiji => nananiji_lists(),
+        ListName::Hanshin => hanshin_lists(allow_split),
+        ListName::Kyojin => kyojin_lists(allow_split),
+    }
+}
+
+// --cache-file, or the file named after the parameters in the cache directory
+fn cache_path(matches: &ArgMatches, list_name: ListName, allow_split: bool, params: &SearchParams) -> PathBuf {
+    if let Some(cache_file) = matches.value_of("cache_file") {
+        return PathBuf::from(cache_file);
+    }
+
+    let prefix = match (list_name, allow_split) {
+        (ListName::Nananiji, _)    => "nananiji",
+        (ListName::Hanshin, true)  => "hanshin_a",
+        (ListName::Hanshin, false) => "hanshin",
+        (ListName::Kyojin, true)   => "kyojin_a",
+        (ListName::Kyojin, false)  => "kyojin",
+    };
+    // falls back to the current directory if no cache directory is known
+    let cache_dir = matches.value_of("cache_dir")
+        .map(PathBuf::from)
+        .or_else(default_cache_dir)
+        .unwrap_or_default();
+
+    cache_dir.join(params.file_name(prefix))
+}
+
+fn load_generator(filepath: &Path) -> Result<ExpressionGenerator> {
+    let file = File::open(filepath)
+        .map_err(|err| anyhow!("cannot open {}: {} (save it with --write-file first)", filepath.display(), err))?;
+    let file = BufReader::new(file);
+    ExpressionGenerator::load(file).map_err(|err| anyhow!("{}: {}", filepath.display(), err))
 }
 
 fn save_generator(filepath: &Path, generator: &ExpressionGenerator) -> Result<()> {
-    let u8_encoded = bincode::serialize(&generator)?;
-    let mut file = File::create(filepath)?;
-    file.write_all(&u8_encoded[..])?;
+    if let Some(dir) = filepath.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file = io::BufWriter::new(File::create(filepath)?);
+    generator.save(file)?;
 
     Ok(())
 }
+
+fn load_search(filepath: &Path) -> Result<RationalSearch> {
+    let file = BufReader::new(File::open(filepath)?);
+    RationalSearch::load(file).map_err(|err| anyhow!("{}: {}", filepath.display(), err))
+}
+
+// writes to a temporary file first, so that an interrupted checkpoint keeps the previous one
+fn save_search(filepath: &Path, rat_search: &RationalSearch) -> Result<()> {
+    let tmp_filepath = filepath.with_extension("tmp");
+    rat_search.save(io::BufWriter::new(File::create(&tmp_filepath)?))?;
+    std::fs::rename(&tmp_filepath, filepath)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn batch_extremes() {
+        let generator = ExpressionGenerator::new_nananiji(2, 10);
+        let inputs = [&i64::MIN.to_string()[..], &i64::MAX.to_string()[..]];
+        for (record, &input) in batch_records(&generator, &inputs, None).into_iter().zip(&inputs) {
+            assert_eq!(record.error, None);
+            let num = input.parse::<i64>().unwrap();
+            assert_eq!(generator.generate_expr(num).unwrap().eval(), Some(num.into()));
+        }
+
+        let mut out = Vec::new();
+        for record in batch_records(&generator, &["3463", "9223372036854775808"], None) {
+            write_record(&mut out, record, BatchFormatName::Csv).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "3463,((2+2*7)*227-((22-7)+(22*7))),4,\n\
+             9223372036854775808,,,\"invalid number \"\"9223372036854775808\"\": number too large to fit in target type\"\n"
+        );
+    }
+
+    #[test]
+    fn batch_threads() {
+        let matches = app().get_matches_from(vec!["nananiji-calculator", "-d", "2", "--range", "1..4", "--threads", "2"]);
+        // the parallel search starts the global thread pool before the batch
+        let search = build_search(&nananiji_lists(), SeedOrder::Strict, &matches).unwrap();
+        let generator = ExpressionGenerator::from_search(&search);
+
+        let mut out = Vec::new();
+        let targets = parse_range(matches.value_of("range").unwrap()).unwrap().map(|num| Ok(num.to_string()));
+        run_batch(&generator, targets, &matches, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "input,expr,copies,error\n\
+             1,((22*7)/(22*7)),2,\n\
+             2,((2*(2-7))-(2-2*7)),2,\n\
+             3,(((2-2)/7)-(2*2-7)),2,\n"
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use crate::limits::{DenomCut, SearchLimits};
+use crate::operator::{Operator, OPERATORS};
+use crate::seed::SeedOrder;
+use bincode::Options;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use simple_error::{SimpleError, bail};
+use std::{env, fmt, io::{self, Read, Write}, path::PathBuf};
+
+const MAGIC: &[u8; 8] = b"NNJCALC\0";
+/// The version of the cache file layout. Files of other versions are rejected.
+pub const CACHE_FORMAT_VERSION: u32 = 1;
+/// The environment variable which overrides the directory of cache files.
+pub const CACHE_DIR_ENV: &str = "NANANIJI_CACHE_DIR";
+
+/// The directory of cache files: `$NANANIJI_CACHE_DIR`, `$XDG_CACHE_HOME/nananiji-calculator`
+/// or `$HOME/.cache/nananiji-calculator`, whichever is set first.
+pub fn default_cache_dir() -> Option<PathBuf> {
+    let var = |name| env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+
+    var(CACHE_DIR_ENV)
+        // relative paths in XDG_CACHE_HOME are invalid and ignored by the specification
+        .or_else(|| var("XDG_CACHE_HOME").filter(|dir| dir.is_absolute()).map(|dir| dir.join("nananiji-calculator")))
+        .or_else(|| var("HOME").map(|dir| dir.join(".cache").join("nananiji-calculator")))
+}
+
+/// The parameters which a search, or a generator built from it, depends on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SearchParams {
+    pub num_lists: Vec<Vec<i64>>,
+    pub seed_order: SeedOrder,
+    pub search_depth: usize,
+    pub denom_cut: DenomCut,
+    pub limits: SearchLimits,
+}
+
+impl SearchParams {
+    /// The name of the cache file of these parameters, e.g. `nananiji-d3-c10-strict.bin`,
+    /// so that the files of different depths or cuts do not overwrite each other.
+    pub fn file_name(&self, prefix: &str) -> String {
+        let mut limits = String::new();
+        if let Some(max_entries) = self.limits.max_entries_per_depth {
+            limits += &format!("-e{}", max_entries);
+        }
+        if let Some(max_numer) = self.limits.max_numer {
+            limits += &format!("-n{}", max_numer);
+        }
+        if let Some(memory_budget) = self.limits.memory_budget {
+            limits += &format!("-m{}", memory_budget);
+        }
+
+        format!("{}-d{}-c{}{}-{}.bin",
+            prefix,
+            self.search_depth,
+            self.denom_cut.to_string().replace(',', "_"),
+            limits,
+            match self.seed_order {
+                SeedOrder::Strict => "strict",
+                SeedOrder::WithinGroup => "withingroup",
+                SeedOrder::AcrossExpression => "acrossexpression",
+            })
+    }
+
+    /// Fails with the differences from `expected` if any.
+    pub fn check(&self, expected: &SearchParams) -> Result<(), SimpleError> {
+        let mut diffs = Vec::new();
+        if self.num_lists != expected.num_lists {
+            diffs.push(format!("seeds {:?} instead of {:?}", self.num_lists, expected.num_lists));
+        }
+        if self.seed_order != expected.seed_order {
+            diffs.push(format!("seed order {:?} instead of {:?}", self.seed_order, expected.seed_order));
+        }
+        if self.search_depth != expected.search_depth {
+            diffs.push(format!("search depth {} instead of {}", self.search_depth, expected.search_depth));
+        }
+        if self.denom_cut != expected.denom_cut {
+            diffs.push(format!("denominator cut {} instead of {}", self.denom_cut, expected.denom_cut));
+        }
+        if self.limits != expected.limits {
+            diffs.push(format!("{} instead of {}", self.limits, expected.limits));
+        }
+
+        if diffs.is_empty() {
+            Ok(())
+        } else {
+            bail!("the cache was built with {}", diffs.join(", "))
+        }
+    }
+}
+
+/// What a cache file contains.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CacheKind {
+    Generator,
+    Search,
+}
+
+impl fmt::Display for CacheKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CacheKind::Generator => write!(f, "generator"),
+            CacheKind::Search => write!(f, "search"),
+        }
+    }
+}
+
+// follows the magic bytes and the format version
+#[derive(Serialize, Deserialize, Debug)]
+struct CacheHeader {
+    crate_version: String,
+    kind: CacheKind,
+    operators: Vec<Operator>,
+    params: SearchParams,
+    payload_len: u64,
+}
+
+// the payload follows the header, and the checksum of the payload follows the payload
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+}
+
+pub(crate) fn write_cache<T: Serialize>(
+    mut writer: impl Write,
+    kind: CacheKind,
+    params: &SearchParams,
+    payload: &T,
+) -> Result<(), SimpleError> {
+    let write = |writer: &mut dyn Write| -> Result<(), Box<bincode::ErrorKind>> {
+        let header = CacheHeader {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            kind,
+            operators: OPERATORS.to_vec(),
+            params: params.clone(),
+            payload_len: options().serialized_size(payload)?,
+        };
+        writer.write_all(MAGIC)?;
+        writer.write_all(&CACHE_FORMAT_VERSION.to_le_bytes())?;
+        options().serialize_into(&mut *writer, &header)?;
+
+        let mut hashing = HashingWriter { inner: &mut *writer, hash: Fnv::default() };
+        options().serialize_into(&mut hashing, payload)?;
+        let checksum = hashing.hash.0;
+        writer.write_all(&checksum.to_le_bytes())?;
+        writer.flush()?;
+        Ok(())
+    };
+    write(&mut writer).map_err(|err| SimpleError::with("cannot write the cache", err))
+}
+
+pub(crate) fn read_cache<T: DeserializeOwned>(
+    mut reader: impl Read,
+    kind: CacheKind,
+) -> Result<(SearchParams, T), SimpleError> {
+    let mut magic = [0; MAGIC.len()];
+    if reader.read_exact(&mut magic).is_err() || &magic != MAGIC {
+        bail!("not a cache file of nananiji-calculator");
+    }
+    let mut version = [0; 4];
+    reader.read_exact(&mut version).map_err(|err| SimpleError::with("the cache is truncated", err))?;
+    let version = u32::from_le_bytes(version);
+    if version != CACHE_FORMAT_VERSION {
+        bail!("the cache format version {} is not supported (expected {}); rebuild the cache",
+            version, CACHE_FORMAT_VERSION);
+    }
+
+    let header: CacheHeader = options()
+        .deserialize_from(&mut reader)
+        .map_err(|err| SimpleError::with("the cache header is corrupted", err))?;
+    if header.kind != kind {
+        bail!("the cache contains a {} instead of a {}", header.kind, kind);
+    }
+    if header.operators != OPERATORS {
+        bail!("the cache was built with the operators {:?} instead of {:?}", header.operators, OPERATORS);
+    }
+
+    let corrupted = || format!("the cache is truncated or corrupted (written by version {})", header.crate_version);
+    let mut hashing = HashingReader { inner: (&mut reader).take(header.payload_len), hash: Fnv::default() };
+    let payload = options()
+        .with_limit(header.payload_len)
+        .deserialize_from(&mut hashing)
+        .map_err(|err| SimpleError::with(&corrupted(), err))?;
+    let (rest, hash) = (hashing.inner.limit(), hashing.hash.0);
+    let mut checksum = [0; 8];
+    if rest != 0 || reader.read_exact(&mut checksum).is_err() || u64::from_le_bytes(checksum) != hash {
+        bail!("{}", corrupted());
+    }
+
+    Ok((header.params, payload))
+}
+
+// 64-bit FNV-1a
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3);
+        }
+    }
+}
+
+// hashes the bytes written through it
+struct HashingWriter<W> {
+    inner: W,
+    hash: Fnv,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.hash.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// hashes the bytes read through it
+struct HashingReader<R> {
+    inner: R,
+    hash: Fnv,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.hash.update(&buf[..len]);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn params() -> SearchParams {
+        SearchParams {
+            num_lists: vec![vec![227], vec![22, 7]],
+            seed_order: SeedOrder::Strict,
+            search_depth: 3,
+            denom_cut: DenomCut::Fixed(10),
+            limits: SearchLimits::default(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut bytes = Vec::new();
+        write_cache(&mut bytes, CacheKind::Search, &params(), &vec![1, 2, 3]).unwrap();
+
+        let (read_params, payload): (_, Vec<i32>) = read_cache(&bytes[..], CacheKind::Search).unwrap();
+        assert_eq!(read_params, params());
+        assert_eq!(payload, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn invalid_cache() {
+        let mut bytes = Vec::new();
+        write_cache(&mut bytes, CacheKind::Search, &params(), &vec![1, 2, 3]).unwrap();
+        let error = |bytes: &[u8], kind| read_cache::<Vec<i32>>(bytes, kind).unwrap_err().to_string();
+
+        assert_eq!(error(b"not a cache", CacheKind::Search), "not a cache file of nananiji-calculator");
+        assert!(error(&bytes, CacheKind::Generator).contains("search instead of a generator"));
+        assert!(error(&bytes[..bytes.len() - 1], CacheKind::Search).contains("truncated"));
+
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(error(&corrupted, CacheKind::Search).contains("corrupted"));
+
+        let mut future = bytes;
+        future[MAGIC.len()] += 1;
+        assert!(error(&future, CacheKind::Search).contains("format version 2 is not supported"));
+    }
+
+    #[test]
+    fn file_name() {
+        assert_eq!(params().file_name("nananiji"), "nananiji-d3-c10-strict.bin");
+
+        let params = SearchParams {
+            seed_order: SeedOrder::WithinGroup,
+            denom_cut: DenomCut::Schedule(vec![30, 10, 5]),
+            ..params()
+        };
+        assert_eq!(params.file_name("hanshin_a"), "hanshin_a-d3-c30_10_5-withingroup.bin");
+
+        let limits = SearchLimits { max_entries_per_depth: Some(5000), memory_budget: Some(1 << 20), max_numer: None };
+        let params = SearchParams { limits, ..params };
+        assert_eq!(params.file_name("hanshin_a"), "hanshin_a-d3-c30_10_5-e5000-m1048576-withingroup.bin");
+
+        let params = SearchParams { seed_order: SeedOrder::AcrossExpression, ..params };
+        assert_eq!(params.file_name("hanshin_a"), "hanshin_a-d3-c30_10_5-e5000-m1048576-acrossexpression.bin");
+    }
+
+    #[test]
+    fn check_params() {
+        assert!(params().check(&params()).is_ok());
+
+        let deeper = SearchParams { search_depth: 4, denom_cut: DenomCut::Fixed(5), ..params() };
+        assert_eq!(
+            deeper.check(&params()).unwrap_err().to_string(),
+            "the cache was built with search depth 4 instead of 3, denominator cut 5 instead of 10"
+        );
+
+        let limited = SearchParams {
+            limits: SearchLimits { max_numer: Some(100), ..SearchLimits::default() },
+            ..params()
+        };
+        assert_eq!(
+            limited.check(&params()).unwrap_err().to_string(),
+            "the cache was built with max numerator 100 instead of no limits"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use crate::operator::Operator;
+use num::rational::Rational64;
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+/// Expression tree shared by the generators and the formatters.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Num(i64),
+    /// `paren` requests explicit parentheses even where the precedence does not need them.
+    BinOp { op: Operator, lhs: Box<Expr>, rhs: Box<Expr>, paren: bool },
+}
+
+/// An intermediate result of [`Expr::trace`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    pub expr: Expr,
+    pub value: Rational64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    Markdown,
+}
+
+impl Expr {
+    pub fn binop(op: Operator, lhs: Expr, rhs: Expr, paren: bool) -> Self {
+        Expr::BinOp { op, lhs: Box::new(lhs), rhs: Box::new(rhs), paren }
+    }
+
+    // the same expression whose outermost operation is explicitly parenthesized
+    pub(crate) fn with_paren(mut self) -> Self {
+        if let Expr::BinOp { paren, .. } = &mut self {
+            *paren = true;
+        }
+        self
+    }
+
+    /// Evaluates the expression. Returns `None` on division by zero.
+    pub fn eval(&self) -> Option<Rational64> {
+        match self {
+            Expr::Num(num) => Some((*num).into()),
+            Expr::BinOp { op, lhs, rhs, .. } => op.invoke(lhs.eval()?, rhs.eval()?),
+        }
+    }
+
+    /// Evaluates the expression bottom-up and returns every intermediate result.
+    pub fn trace(&self) -> Option<Vec<TraceStep>> {
+        let mut steps = Vec::new();
+        self.trace_impl(&mut steps)?;
+        if steps.is_empty() {
+            steps.push(TraceStep { expr: self.clone(), value: self.eval()? });
+        }
+
+        Some(steps)
+    }
+
+    fn trace_impl(&self, steps: &mut Vec<TraceStep>) -> Option<Rational64> {
+        match self {
+            Expr::Num(num) => Some((*num).into()),
+            Expr::BinOp { op, lhs, rhs, .. } => {
+                let lval = lhs.trace_impl(steps)?;
+                let rval = rhs.trace_impl(steps)?;
+                let value = op.invoke(lval, rval)?;
+                steps.push(TraceStep { expr: self.clone(), value });
+                Some(value)
+            },
+        }
+    }
+
+    // `parent` is the operator of the parent node and whether self is its rhs
+    fn fmt_in(&self, f: &mut fmt::Formatter, parent: Option<(Operator, bool)>) -> fmt::Result {
+        match self {
+            // a negative operand such as `2-(-3)` would read as `2--3`
+            Expr::Num(num) if *num < 0 && parent.is_some() => write!(f, "({})", num),
+            Expr::Num(num) => write!(f, "{}", num),
+            Expr::BinOp { op, lhs, rhs, paren } => {
+                let needed = needs_paren(*op, parent);
+                if *paren || needed {
+                    write!(f, "(")?;
+                }
+                lhs.fmt_in(f, Some((*op, false)))?;
+                write!(f, "{}", op)?;
+                rhs.fmt_in(f, Some((*op, true)))?;
+                if *paren || needed {
+                    write!(f, ")")?;
+                }
+
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Prints the expression with the requested parentheses and those required by the precedence,
+/// so the text always evaluates to the same value, e.g. `22/(2*7)` rather than `22/2*7`.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_in(f, None)
+    }
+}
+
+// whether a node of `op` needs parentheses under `parent` (its operator and whether the node is its rhs)
+pub(crate) fn needs_paren(op: Operator, parent: Option<(Operator, bool)>) -> bool {
+    match parent {
+        Some((parent_op, is_rhs)) => op.precedence() < parent_op.precedence() ||
+            (is_rhs && op.precedence() == parent_op.precedence() && parent_op.is_right_sensitive()),
+        None => false,
+    }
+}
+
+pub fn render_trace(steps: &[TraceStep], format: TraceFormat) -> String {
+    match format {
+        TraceFormat::Text => {
+            steps
+                .iter()
+                .map(|step| format!("{} = {}\n", step.expr, step.value))
+                .collect()
+        },
+        TraceFormat::Markdown => {
+            let mut ret = "| step | expression | value |\n|---:|---|---:|\n".to_string();
+            for (i, step) in steps.iter().enumerate() {
+                ret += &format!("| {} | `{}` | {} |\n", i + 1, step.expr, step.value);
+            }
+            ret
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // (22-7)*(2+2*7)
+    fn sample() -> Expr {
+        Expr::binop(
+            Operator::Mul,
+            Expr::binop(Operator::Sub, Expr::Num(22), Expr::Num(7), true),
+            Expr::binop(
+                Operator::Add,
+                Expr::Num(2),
+                Expr::binop(Operator::Mul, Expr::Num(2), Expr::Num(7), false),
+                true),
+            false)
+    }
+
+    #[test]
+    fn fmt() {
+        assert_eq!(sample().to_string(), "(22-7)*(2+2*7)");
+
+        // parentheses required by the precedence are never omitted
+        let expr = Expr::binop(
+            Operator::Div,
+            Expr::Num(2),
+            Expr::binop(Operator::Mul, Expr::Num(2), Expr::Num(7), false),
+            false);
+        assert_eq!(expr.to_string(), "2/(2*7)");
+
+        let expr = Expr::binop(
+            Operator::Add,
+            Expr::Num(2),
+            Expr::binop(Operator::Sub, Expr::Num(2), Expr::Num(7), false),
+            false);
+        assert_eq!(expr.to_string(), "2+2-7");
+
+        // negative operands are parenthesized
+        assert_eq!(Expr::binop(Operator::Sub, Expr::Num(2), Expr::Num(-3), false).to_string(), "2-(-3)");
+        assert_eq!(Expr::Num(-3).to_string(), "-3");
+    }
+
+    #[test]
+    fn eval() {
+        assert_eq!(sample().eval(), Some(240.into()));
+        assert_eq!(Expr::binop(Operator::Div, Expr::Num(2), Expr::Num(0), true).eval(), None);
+    }
+
+    #[test]
+    fn trace() {
+        let steps = sample().trace().unwrap();
+        let values: Vec<_> = steps.iter().map(|step| step.value).collect();
+
+        assert_eq!(values, vec![15.into(), 14.into(), 16.into(), 240.into()]);
+        assert_eq!(render_trace(&steps[..2], TraceFormat::Text), "(22-7) = 15\n2*7 = 14\n");
+        assert_eq!(
+            render_trace(&steps[3..], TraceFormat::Markdown),
+            "| step | expression | value |\n|---:|---|---:|\n| 1 | `(22-7)*(2+2*7)` | 240 |\n"
+        );
+        assert_eq!(Expr::Num(227).trace().unwrap()[0].value, 227.into());
+    }
+}
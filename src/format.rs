@@ -0,0 +1,42 @@
+mod code;
+mod dot;
+mod json;
+mod latex;
+mod mathml;
+mod reading;
+mod rpn;
+mod sexpr;
+mod svg;
+mod unicode;
+
+pub use self::code::{CodeLanguage, to_code};
+pub use self::dot::to_dot;
+pub use self::json::{JsonExpr, to_json_ast};
+pub use self::latex::to_latex;
+pub use self::mathml::{to_mathml, to_mathml_equation};
+pub use self::reading::to_japanese_reading;
+pub use self::rpn::{parse_rpn, to_rpn};
+pub use self::sexpr::{parse_sexpr, to_sexpr};
+pub use self::svg::{SvgLayout, SvgOptions, to_svg};
+pub use self::unicode::{UnicodeOptions, to_unicode};
+
+#[cfg(test)]
+use crate::{Expr, Operator};
+
+// ((22/7)*(2+2*7)-227)
+#[cfg(test)]
+pub(crate) fn sample_expr() -> Expr {
+    Expr::binop(
+        Operator::Sub,
+        Expr::binop(
+            Operator::Mul,
+            Expr::binop(Operator::Div, Expr::Num(22), Expr::Num(7), true),
+            Expr::binop(
+                Operator::Add,
+                Expr::Num(2),
+                Expr::binop(Operator::Mul, Expr::Num(2), Expr::Num(7), false),
+                true),
+            false),
+        Expr::Num(227),
+        true)
+}
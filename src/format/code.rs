@@ -0,0 +1,130 @@
+use crate::{Expr, Operator};
+use crate::expr::needs_paren;
+use num::rational::Rational64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeLanguage {
+    Rust,
+    Python,
+    JavaScript,
+}
+
+// rational helpers on [numerator, denominator] pairs of BigInt, since JavaScript has no rational type
+const JS_PRELUDE: &str = "\
+const gcd = (a, b) => (b === 0n ? (a < 0n ? -a : a) : gcd(b, a % b));
+const norm = ([n, d]) => {
+  if (d === 0n) throw new RangeError('division by zero');
+  const g = gcd(n, d) * (d < 0n ? -1n : 1n);
+  return [n / g, d / g];
+};
+const r = (n, d = 1n) => norm([n, d]);
+const add = ([a, b], [c, d]) => norm([a * d + c * b, b * d]);
+const sub = ([a, b], [c, d]) => norm([a * d - c * b, b * d]);
+const mul = ([a, b], [c, d]) => norm([a * c, b * d]);
+const div = ([a, b], [c, d]) => norm([a * d, b * c]);
+";
+
+/// Renders a self-checking snippet which evaluates `expr` in exact rational arithmetic
+/// and asserts that it equals `target`.
+pub fn to_code(expr: &Expr, target: Rational64, lang: CodeLanguage) -> String {
+    let (numer, denom) = (target.numer(), target.denom());
+    match lang {
+        CodeLanguage::Rust => format!(
+            concat!(
+                "// [dependencies] num = \"0.3\"\n",
+                "use num::{{BigInt, BigRational}};\n",
+                "\n",
+                "fn main() {{\n",
+                "    let r = |n: i64| BigRational::from_integer(BigInt::from(n));\n",
+                "    assert_eq!({}, BigRational::new(BigInt::from({}), BigInt::from({})));\n",
+                "}}\n",
+            ),
+            infix(expr, None, &|num| format!("r({})", num)), numer, denom),
+        CodeLanguage::Python => format!(
+            concat!(
+                "from fractions import Fraction\n",
+                "\n",
+                "assert {} == Fraction({}, {})\n",
+            ),
+            infix(expr, None, &|num| format!("Fraction({})", num)), numer, denom),
+        CodeLanguage::JavaScript => format!(
+            concat!(
+                "{}",
+                "\n",
+                "const [n, d] = {};\n",
+                "if (n !== {}n || d !== {}n) throw new Error(`unexpected value: ${{n}}/${{d}}`);\n",
+            ),
+            JS_PRELUDE, js_calls(expr), numer, denom),
+    }
+}
+
+fn infix(expr: &Expr, parent: Option<(Operator, bool)>, leaf: &dyn Fn(i64) -> String) -> String {
+    match expr {
+        Expr::Num(num) => leaf(*num),
+        Expr::BinOp { op, lhs, rhs, .. } => {
+            let body = format!("{} {} {}",
+                infix(lhs, Some((*op, false)), leaf),
+                op,
+                infix(rhs, Some((*op, true)), leaf));
+
+            if needs_paren(*op, parent) {
+                format!("({})", body)
+            } else {
+                body
+            }
+        },
+    }
+}
+
+fn js_calls(expr: &Expr) -> String {
+    match expr {
+        Expr::Num(num) => format!("r({}n)", num),
+        Expr::BinOp { op, lhs, rhs, .. } => {
+            let func = match op {
+                Operator::Add => "add",
+                Operator::Sub => "sub",
+                Operator::Mul => "mul",
+                Operator::Div => "div",
+            };
+            format!("{}({}, {})", func, js_calls(lhs), js_calls(rhs))
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::sample_expr;
+
+    #[test]
+    fn rust() {
+        let code = to_code(&sample_expr(), Rational64::new(-1237, 7), CodeLanguage::Rust);
+
+        assert!(code.contains(
+            "    assert_eq!(r(22) / r(7) * (r(2) + r(2) * r(7)) - r(227), \
+             BigRational::new(BigInt::from(-1237), BigInt::from(7)));\n"
+        ));
+    }
+
+    #[test]
+    fn python() {
+        assert_eq!(
+            to_code(&sample_expr(), Rational64::new(-1237, 7), CodeLanguage::Python),
+            concat!(
+                "from fractions import Fraction\n",
+                "\n",
+                "assert Fraction(22) / Fraction(7) * (Fraction(2) + Fraction(2) * Fraction(7)) - Fraction(227) ",
+                "== Fraction(-1237, 7)\n",
+            )
+        );
+    }
+
+    #[test]
+    fn javascript() {
+        let code = to_code(&sample_expr(), Rational64::new(-1237, 7), CodeLanguage::JavaScript);
+
+        assert!(code.starts_with(JS_PRELUDE));
+        assert!(code.contains("const [n, d] = sub(mul(div(r(22n), r(7n)), add(r(2n), mul(r(2n), r(7n)))), r(227n));\n"));
+        assert!(code.contains("if (n !== -1237n || d !== 7n) throw "));
+    }
+}
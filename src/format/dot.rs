@@ -0,0 +1,95 @@
+use crate::Expr;
+use crate::seed::split_seeds;
+
+// fill colors of the leaves, indexed by the seed group they belong to
+const LEAF_COLORS: [&str; 6] = ["#f4a582", "#92c5de", "#a6dba0", "#c2a5cf", "#fee090", "#d9d9d9"];
+const UNKNOWN_COLOR: &str = "white";
+
+/// Renders `expr` as a Graphviz digraph.
+///
+/// Internal nodes are labeled with the operator and their value, and leaves are colored by
+/// the group of `num_lists` they come from (e.g. `227` vs `22, 7` vs `2, 2, 7`).
+pub fn to_dot(expr: &Expr, num_lists: &[Vec<i64>]) -> String {
+    let groups = split_seeds(&leaves(expr), num_lists);
+
+    let mut ret = "digraph expr {\n    ordering=out;\n    node [style=filled];\n".to_string();
+    let (mut node_id, mut leaf_id) = (0, 0);
+    write_node(expr, groups.as_deref(), &mut node_id, &mut leaf_id, &mut ret);
+    ret += "}\n";
+
+    ret
+}
+
+fn leaves(expr: &Expr) -> Vec<i64> {
+    match expr {
+        Expr::Num(num) => vec![*num],
+        Expr::BinOp { lhs, rhs, .. } => {
+            let mut ret = leaves(lhs);
+            ret.extend(leaves(rhs));
+            ret
+        },
+    }
+}
+
+// writes the subtree in pre-order and returns the id of its root
+fn write_node(
+    expr: &Expr,
+    groups: Option<&[usize]>,
+    node_id: &mut usize,
+    leaf_id: &mut usize,
+    out: &mut String,
+) -> usize {
+    let id = *node_id;
+    *node_id += 1;
+
+    match expr {
+        Expr::Num(num) => {
+            let color = match groups {
+                Some(groups) => LEAF_COLORS[groups[*leaf_id] % LEAF_COLORS.len()],
+                None => UNKNOWN_COLOR,
+            };
+            *leaf_id += 1;
+            *out += &format!("    n{} [label=\"{}\", shape=box, fillcolor=\"{}\"];\n", id, num, color);
+        },
+        Expr::BinOp { op, lhs, rhs, .. } => {
+            let value = match expr.eval() {
+                Some(value) => value.to_string(),
+                None => "undefined".to_string(),
+            };
+            *out += &format!("    n{} [label=\"{}\\n{}\", shape=ellipse, fillcolor=white];\n", id, op, value);
+
+            let lhs_id = write_node(lhs, groups, node_id, leaf_id, out);
+            let rhs_id = write_node(rhs, groups, node_id, leaf_id, out);
+            *out += &format!("    n{} -> n{};\n    n{} -> n{};\n", id, lhs_id, id, rhs_id);
+        },
+    }
+
+    id
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::sample_expr;
+
+    #[test]
+    fn dot() {
+        let dot = to_dot(&sample_expr(), &crate::nananiji_lists());
+
+        assert!(dot.starts_with("digraph expr {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("    n0 [label=\"-\\n-1237/7\", shape=ellipse, fillcolor=white];\n"));
+        assert!(dot.contains("    n2 [label=\"/\\n22/7\", shape=ellipse, fillcolor=white];\n"));
+        assert!(dot.contains("    n3 [label=\"22\", shape=box, fillcolor=\"#92c5de\"];\n"));
+        assert!(dot.contains("    n6 [label=\"2\", shape=box, fillcolor=\"#a6dba0\"];\n"));
+        assert!(dot.contains("    n10 [label=\"227\", shape=box, fillcolor=\"#f4a582\"];\n"));
+        assert!(dot.contains("    n0 -> n1;\n    n0 -> n10;\n"));
+    }
+
+    #[test]
+    fn unknown_split() {
+        let dot = to_dot(&sample_expr(), &[vec![227]]);
+
+        assert!(dot.contains("    n10 [label=\"227\", shape=box, fillcolor=\"white\"];\n"));
+    }
+}
@@ -0,0 +1,77 @@
+use crate::Expr;
+use serde::{Serialize, Deserialize};
+use serde_json::{Value, json};
+
+/// JSON document describing an expression, its value, copy count and text length.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JsonExpr {
+    pub expr: String,
+    pub ast: Value,
+    /// `None` if the expression divides by zero.
+    pub value: Option<String>,
+    pub copies: Option<usize>,
+    pub length: usize,
+}
+
+impl JsonExpr {
+    pub fn new(expr: &Expr, copies: Option<usize>) -> Self {
+        let text = expr.to_string();
+
+        Self {
+            length: text.chars().count(),
+            expr: text,
+            ast: to_json_ast(expr),
+            value: expr.eval().map(|value| value.to_string()),
+            copies,
+        }
+    }
+}
+
+/// Converts `expr` into nested JSON nodes such as `{"op":"+","lhs":..,"rhs":..}` and `{"seed":227}`.
+pub fn to_json_ast(expr: &Expr) -> Value {
+    match expr {
+        Expr::Num(num) => json!({ "seed": num }),
+        Expr::BinOp { op, lhs, rhs, .. } => json!({
+            "op": op.to_string(),
+            "lhs": to_json_ast(lhs),
+            "rhs": to_json_ast(rhs),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::sample_expr;
+
+    #[test]
+    fn json_ast() {
+        assert_eq!(to_json_ast(&Expr::Num(227)).to_string(), r#"{"seed":227}"#);
+        assert_eq!(
+            to_json_ast(&sample_expr()),
+            json!({
+                "op": "-",
+                "lhs": {
+                    "op": "*",
+                    "lhs": { "op": "/", "lhs": { "seed": 22 }, "rhs": { "seed": 7 } },
+                    "rhs": {
+                        "op": "+",
+                        "lhs": { "seed": 2 },
+                        "rhs": { "op": "*", "lhs": { "seed": 2 }, "rhs": { "seed": 7 } },
+                    },
+                },
+                "rhs": { "seed": 227 },
+            })
+        );
+    }
+
+    #[test]
+    fn json_expr() {
+        let json_expr = JsonExpr::new(&sample_expr(), Some(3));
+
+        assert_eq!(json_expr.expr, "((22/7)*(2+2*7)-227)");
+        assert_eq!(json_expr.value, Some("-1237/7".to_string()));
+        assert_eq!(json_expr.copies, Some(3));
+        assert_eq!(json_expr.length, 20);
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{Expr, Operator};
+use crate::expr::needs_paren;
+
+/// Renders `expr` as LaTeX math with `\frac` for divisions and only the necessary parentheses.
+pub fn to_latex(expr: &Expr) -> String {
+    latex_in(expr, None)
+}
+
+fn latex_in(expr: &Expr, parent: Option<(Operator, bool)>) -> String {
+    match expr {
+        Expr::Num(num) if *num < 0 && parent.is_some() => format!("\\left({}\\right)", num),
+        Expr::Num(num) => num.to_string(),
+        Expr::BinOp { op: Operator::Div, lhs, rhs, .. } => {
+            // the fraction bar groups both operands by itself
+            format!("\\frac{{{}}}{{{}}}", latex_in(lhs, None), latex_in(rhs, None))
+        },
+        Expr::BinOp { op, lhs, rhs, .. } => {
+            let symbol = match op {
+                Operator::Add => "+",
+                Operator::Sub => "-",
+                _ => "\\times",
+            };
+            let body = format!("{} {} {}",
+                latex_in(lhs, Some((*op, false))),
+                symbol,
+                latex_in(rhs, Some((*op, true))));
+
+            if needs_paren(*op, parent) {
+                format!("\\left({}\\right)", body)
+            } else {
+                body
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::sample_expr;
+
+    #[test]
+    fn latex() {
+        assert_eq!(
+            to_latex(&sample_expr()),
+            "\\frac{22}{7} \\times \\left(2 + 2 \\times 7\\right) - 227"
+        );
+
+        let expr = Expr::binop(
+            Operator::Div,
+            Expr::binop(Operator::Sub, Expr::Num(22), Expr::Num(7), true),
+            Expr::binop(Operator::Mul, Expr::Num(2), Expr::Num(7), false),
+            true);
+        assert_eq!(to_latex(&expr), "\\frac{22 - 7}{2 \\times 7}");
+    }
+}
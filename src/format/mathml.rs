@@ -0,0 +1,94 @@
+use crate::{Expr, Operator};
+use crate::expr::needs_paren;
+use num::rational::Rational64;
+
+/// Renders `expr` as presentation MathML with `<mfrac>` for divisions.
+pub fn to_mathml(expr: &Expr) -> String {
+    format!("<math xmlns=\"http://www.w3.org/1998/Math/MathML\">{}</math>", mathml_in(expr, None))
+}
+
+/// Renders `expr = value` as a single `<math>` element, so that it can be embedded in a page as is.
+pub fn to_mathml_equation(expr: &Expr, value: Rational64) -> String {
+    let value = if value.is_integer() {
+        Expr::Num(value.to_integer())
+    } else {
+        Expr::binop(Operator::Div, Expr::Num(*value.numer()), Expr::Num(*value.denom()), false)
+    };
+    format!("<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow>{}<mo>=</mo>{}</mrow></math>",
+        mathml_in(expr, None),
+        mathml_in(&value, None))
+}
+
+fn mathml_in(expr: &Expr, parent: Option<(Operator, bool)>) -> String {
+    match expr {
+        // a negative operand such as `2-(-3)` would read as `2--3`
+        Expr::Num(num) if *num < 0 => {
+            let body = format!("<mo>&#x2212;</mo><mn>{}</mn>", num.unsigned_abs());
+            if parent.is_some() {
+                format!("<mrow><mo>(</mo>{}<mo>)</mo></mrow>", body)
+            } else {
+                format!("<mrow>{}</mrow>", body)
+            }
+        },
+        Expr::Num(num) => format!("<mn>{}</mn>", num),
+        Expr::BinOp { op: Operator::Div, lhs, rhs, .. } => {
+            format!("<mfrac>{}{}</mfrac>", mathml_in(lhs, None), mathml_in(rhs, None))
+        },
+        Expr::BinOp { op, lhs, rhs, .. } => {
+            let symbol = match op {
+                Operator::Add => "+",
+                Operator::Sub => "&#x2212;",
+                _ => "&#xD7;",
+            };
+            let body = format!("{}<mo>{}</mo>{}",
+                mathml_in(lhs, Some((*op, false))),
+                symbol,
+                mathml_in(rhs, Some((*op, true))));
+
+            if needs_paren(*op, parent) {
+                format!("<mrow><mo>(</mo>{}<mo>)</mo></mrow>", body)
+            } else {
+                format!("<mrow>{}</mrow>", body)
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::sample_expr;
+
+    #[test]
+    fn mathml() {
+        assert_eq!(to_mathml(&Expr::Num(227)), "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mn>227</mn></math>");
+        assert_eq!(
+            mathml_in(&sample_expr(), None),
+            concat!(
+                "<mrow><mrow><mfrac><mn>22</mn><mn>7</mn></mfrac><mo>&#xD7;</mo>",
+                "<mrow><mo>(</mo><mn>2</mn><mo>+</mo><mrow><mn>2</mn><mo>&#xD7;</mo><mn>7</mn></mrow><mo>)</mo></mrow>",
+                "</mrow><mo>&#x2212;</mo><mn>227</mn></mrow>",
+            )
+        );
+        assert_eq!(
+            mathml_in(&Expr::binop(Operator::Sub, Expr::Num(2), Expr::Num(-3), false), None),
+            "<mrow><mn>2</mn><mo>&#x2212;</mo><mrow><mo>(</mo><mo>&#x2212;</mo><mn>3</mn><mo>)</mo></mrow></mrow>"
+        );
+        assert_eq!(mathml_in(&Expr::Num(-3), None), "<mrow><mo>&#x2212;</mo><mn>3</mn></mrow>");
+    }
+
+    #[test]
+    fn equation() {
+        let expr = Expr::binop(Operator::Mul, Expr::Num(22), Expr::Num(7), false);
+        assert_eq!(
+            to_mathml_equation(&expr, 154.into()),
+            concat!(
+                "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow>",
+                "<mrow><mn>22</mn><mo>&#xD7;</mo><mn>7</mn></mrow><mo>=</mo><mn>154</mn>",
+                "</mrow></math>",
+            )
+        );
+        assert!(to_mathml_equation(&sample_expr(), Rational64::new(-1237, 7))
+            .ends_with("<mo>=</mo><mfrac><mrow><mo>&#x2212;</mo><mn>1237</mn></mrow><mn>7</mn></mfrac></mrow></math>"));
+    }
+}
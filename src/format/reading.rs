@@ -0,0 +1,167 @@
+use crate::{Expr, Operator};
+use crate::expr::needs_paren;
+
+const DIGITS: [&str; 10] = ["", "いち", "に", "さん", "よん", "ご", "ろく", "なな", "はち", "きゅう"];
+// readings of 10^4, 10^8, 10^12 and 10^16
+const GROUP_UNITS: [&str; 5] = ["", "まん", "おく", "ちょう", "けい"];
+
+/// Renders `expr` as Japanese reading text in hiragana for text-to-speech,
+/// e.g. `なな ぶんの にじゅうに かける かっこ に たす に かける なな かっことじ`.
+pub fn to_japanese_reading(expr: &Expr) -> String {
+    reading_in(expr, None).join(" ")
+}
+
+fn reading_in(expr: &Expr, parent: Option<(Operator, bool)>) -> Vec<String> {
+    match expr {
+        Expr::Num(num) => vec![japanese_number(*num)],
+        Expr::BinOp { op: Operator::Div, lhs, rhs, .. } => {
+            // a fraction is read from the denominator, and compound operands are parenthesized
+            let mut ret = fraction_operand(rhs);
+            ret.push("ぶんの".to_string());
+            ret.extend(fraction_operand(lhs));
+            ret
+        },
+        Expr::BinOp { op, lhs, rhs, .. } => {
+            let mut ret = reading_in(lhs, Some((*op, false)));
+            ret.push(match op {
+                Operator::Add => "たす",
+                Operator::Sub => "ひく",
+                _ => "かける",
+            }.to_string());
+            ret.extend(reading_in(rhs, Some((*op, true))));
+
+            if needs_paren(*op, parent) {
+                parenthesize(ret)
+            } else {
+                ret
+            }
+        },
+    }
+}
+
+fn fraction_operand(expr: &Expr) -> Vec<String> {
+    match expr {
+        Expr::Num(_) => reading_in(expr, None),
+        Expr::BinOp { .. } => parenthesize(reading_in(expr, None)),
+    }
+}
+
+fn parenthesize(words: Vec<String>) -> Vec<String> {
+    let mut ret = vec!["かっこ".to_string()];
+    ret.extend(words);
+    ret.push("かっことじ".to_string());
+    ret
+}
+
+fn japanese_number(num: i64) -> String {
+    if num == 0 {
+        return "ぜろ".to_string();
+    }
+
+    let mut groups = Vec::new();
+    let mut rest = num.unsigned_abs();
+    while rest > 0 {
+        groups.push((rest % 10000) as usize);
+        rest /= 10000;
+    }
+
+    let mut ret = if num < 0 { "まいなす ".to_string() } else { String::new() };
+    for (i, group) in groups.into_iter().enumerate().rev() {
+        if group == 0 {
+            continue;
+        }
+
+        let mut reading = group_reading(group);
+        let unit = GROUP_UNITS[i];
+        // sokuon before ちょう and けい, e.g. いっちょう and じゅっけい
+        if unit == "ちょう" || unit == "けい" {
+            for (from, to) in &[("いち", "いっ"), ("はち", "はっ"), ("じゅう", "じゅっ"), ("ひゃく", "ひゃっ")] {
+                if reading.ends_with(from) && (*from != "ひゃく" || unit == "けい") {
+                    reading.truncate(reading.len() - from.len());
+                    reading += to;
+                    break;
+                }
+            }
+        }
+        ret += &reading;
+        ret += unit;
+    }
+
+    ret
+}
+
+// reads 1..=9999
+fn group_reading(group: usize) -> String {
+    let (thousands, hundreds, tens, ones) = (group / 1000, group / 100 % 10, group / 10 % 10, group % 10);
+
+    let mut ret = String::new();
+    ret += match thousands {
+        0 => "",
+        1 => "せん",
+        3 => "さんぜん",
+        8 => "はっせん",
+        _ => DIGITS[thousands],
+    };
+    if thousands > 1 && thousands != 3 && thousands != 8 {
+        ret += "せん";
+    }
+
+    ret += match hundreds {
+        0 => "",
+        1 => "ひゃく",
+        3 => "さんびゃく",
+        6 => "ろっぴゃく",
+        8 => "はっぴゃく",
+        _ => DIGITS[hundreds],
+    };
+    if hundreds > 1 && hundreds != 3 && hundreds != 6 && hundreds != 8 {
+        ret += "ひゃく";
+    }
+
+    if tens > 0 {
+        if tens > 1 {
+            ret += DIGITS[tens];
+        }
+        ret += "じゅう";
+    }
+
+    ret += DIGITS[ones];
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::sample_expr;
+
+    #[test]
+    fn number() {
+        assert_eq!(japanese_number(227), "にひゃくにじゅうなな");
+        assert_eq!(japanese_number(22), "にじゅうに");
+        assert_eq!(japanese_number(1), "いち");
+        assert_eq!(japanese_number(0), "ぜろ");
+        assert_eq!(japanese_number(3463), "さんぜんよんひゃくろくじゅうさん");
+        assert_eq!(japanese_number(-34633), "まいなす さんまんよんせんろっぴゃくさんじゅうさん");
+        assert_eq!(japanese_number(1800), "せんはっぴゃく");
+        assert_eq!(japanese_number(10011), "いちまんじゅういち");
+        assert_eq!(japanese_number(100_000_000), "いちおく");
+        assert_eq!(japanese_number(1_0000_0000_0000), "いっちょう");
+        assert_eq!(japanese_number(10_0000_0000_0000), "じゅっちょう");
+        assert_eq!(japanese_number(100_0000_0000_0000_0000), "ひゃっけい");
+    }
+
+    #[test]
+    fn reading() {
+        assert_eq!(
+            to_japanese_reading(&sample_expr()),
+            "なな ぶんの にじゅうに かける かっこ に たす に かける なな かっことじ ひく にひゃくにじゅうなな"
+        );
+
+        let expr = Expr::binop(
+            Operator::Div,
+            Expr::Num(2),
+            Expr::binop(Operator::Sub, Expr::Num(22), Expr::Num(7), false),
+            false);
+        assert_eq!(to_japanese_reading(&expr), "かっこ にじゅうに ひく なな かっことじ ぶんの に");
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{Expr, Operator};
+use simple_error::{SimpleError, bail};
+
+/// Renders `expr` in reverse Polish notation, e.g. `2 2 7 * +`.
+pub fn to_rpn(expr: &Expr) -> String {
+    match expr {
+        Expr::Num(num) => num.to_string(),
+        Expr::BinOp { op, lhs, rhs, .. } => format!("{} {} {}", to_rpn(lhs), to_rpn(rhs), op),
+    }
+}
+
+/// Parses an expression in reverse Polish notation.
+pub fn parse_rpn(s: &str) -> Result<Expr, SimpleError> {
+    let mut stack = Vec::new();
+    for token in s.split_whitespace() {
+        if let Ok(num) = token.parse::<i64>() {
+            stack.push(Expr::Num(num));
+        } else if let Some(op) = Operator::from_symbol(token) {
+            let (rhs, lhs) = match (stack.pop(), stack.pop()) {
+                (Some(rhs), Some(lhs)) => (rhs, lhs),
+                _ => bail!("too few operands for {}", op),
+            };
+            stack.push(Expr::binop(op, lhs, rhs, false));
+        } else {
+            bail!("unknown token: {}", token);
+        }
+    }
+
+    match (stack.pop(), stack.is_empty()) {
+        (Some(expr), true) => Ok(expr),
+        (None, _) => bail!("empty expression"),
+        (Some(_), false) => bail!("too many operands"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::{sample_expr, to_sexpr};
+
+    #[test]
+    fn rpn() {
+        assert_eq!(to_rpn(&sample_expr()), "22 7 / 2 2 7 * + * 227 -");
+    }
+
+    #[test]
+    fn round_trip() {
+        let rpn = to_rpn(&sample_expr());
+        let expr = parse_rpn(&rpn).unwrap();
+
+        assert_eq!(to_rpn(&expr), rpn);
+        // the same tree, though the explicit parentheses are lost
+        assert_eq!(to_sexpr(&expr), to_sexpr(&sample_expr()));
+        assert_eq!(expr.eval(), sample_expr().eval());
+        assert_eq!(expr.to_string(), "22/7*(2+2*7)-227");
+    }
+
+    #[test]
+    fn parse_error() {
+        assert!(parse_rpn("").is_err());
+        assert!(parse_rpn("2 +").is_err());
+        assert!(parse_rpn("2 2").is_err());
+        assert!(parse_rpn("2 2 %").is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use crate::{Expr, Operator};
+use simple_error::{SimpleError, bail};
+
+/// Renders `expr` as a Lisp-style S-expression, e.g. `(+ 2 (* 2 7))`.
+pub fn to_sexpr(expr: &Expr) -> String {
+    match expr {
+        Expr::Num(num) => num.to_string(),
+        Expr::BinOp { op, lhs, rhs, .. } => format!("({} {} {})", op, to_sexpr(lhs), to_sexpr(rhs)),
+    }
+}
+
+/// Parses a binary S-expression such as `(+ 2 (* 2 7))`.
+pub fn parse_sexpr(s: &str) -> Result<Expr, SimpleError> {
+    let spaced = s.replace('(', " ( ").replace(')', " ) ");
+    let tokens: Vec<_> = spaced.split_whitespace().collect();
+
+    let (expr, rest) = parse_tokens(&tokens)?;
+    if !rest.is_empty() {
+        bail!("unexpected tokens after the expression: {}", rest.join(" "));
+    }
+
+    Ok(expr)
+}
+
+// parses one expression from the head of `tokens` and returns the remaining tokens
+fn parse_tokens<'a, 'b>(tokens: &'a [&'b str]) -> Result<(Expr, &'a [&'b str]), SimpleError> {
+    match tokens.split_first() {
+        Some((&"(", rest)) => {
+            let (op, rest) = match rest.split_first() {
+                Some((symbol, rest)) => match Operator::from_symbol(symbol) {
+                    Some(op) => (op, rest),
+                    None => bail!("unknown operator: {}", symbol),
+                },
+                None => bail!("unexpected end of input"),
+            };
+            let (lhs, rest) = parse_tokens(rest)?;
+            let (rhs, rest) = parse_tokens(rest)?;
+
+            match rest.split_first() {
+                Some((&")", rest)) => Ok((Expr::binop(op, lhs, rhs, false), rest)),
+                _ => bail!("{} takes exactly two operands", op),
+            }
+        },
+        Some((token, rest)) => match token.parse::<i64>() {
+            Ok(num) => Ok((Expr::Num(num), rest)),
+            Err(_) => bail!("unexpected token: {}", token),
+        },
+        None => bail!("unexpected end of input"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::{sample_expr, to_rpn};
+
+    #[test]
+    fn sexpr() {
+        assert_eq!(to_sexpr(&sample_expr()), "(- (* (/ 22 7) (+ 2 (* 2 7))) 227)");
+    }
+
+    #[test]
+    fn round_trip() {
+        let sexpr = to_sexpr(&sample_expr());
+        let expr = parse_sexpr(&sexpr).unwrap();
+
+        assert_eq!(to_sexpr(&expr), sexpr);
+        // the same tree, though the explicit parentheses are lost
+        assert_eq!(to_rpn(&expr), to_rpn(&sample_expr()));
+        assert_eq!(expr.eval(), sample_expr().eval());
+        assert_eq!(parse_sexpr(" 227 ").unwrap(), Expr::Num(227));
+    }
+
+    #[test]
+    fn parse_error() {
+        assert!(parse_sexpr("").is_err());
+        assert!(parse_sexpr("(+ 2)").is_err());
+        assert!(parse_sexpr("(+ 2 2 7)").is_err());
+        assert!(parse_sexpr("(% 2 2)").is_err());
+        assert!(parse_sexpr("(+ 2 2))").is_err());
+    }
+}
@@ -0,0 +1,386 @@
+use crate::{Expr, Operator};
+use crate::expr::needs_paren;
+
+// rough glyph metrics relative to the font size, since no font is available to measure with
+const CHAR_WIDTH: f64 = 0.6;
+const PAREN_WIDTH: f64 = 0.35;
+const ASCENT: f64 = 0.8;
+const DESCENT: f64 = 0.2;
+// height of the fraction bar above the baseline
+const AXIS: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SvgLayout {
+    /// Typeset math such as `3463 = (2+2×7)×227−...` with stacked fractions.
+    Typeset,
+    /// Tree diagram whose internal nodes show the operator and their value.
+    Tree,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SvgOptions {
+    pub layout: SvgLayout,
+    /// Width of the image in pixels. The height follows the aspect ratio of the drawing.
+    pub width: f64,
+    pub font_family: String,
+    pub font_size: f64,
+    /// Prefixes the typeset expression with `value = `.
+    pub show_value: bool,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            layout: SvgLayout::Typeset,
+            width: 1200.0,
+            font_family: "serif".to_string(),
+            font_size: 48.0,
+            show_value: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Text { x: f64, y: f64, size: f64, text: String },
+    Line { x1: f64, y1: f64, x2: f64, y2: f64 },
+    Rect { x: f64, y: f64, width: f64, height: f64 },
+}
+
+impl Item {
+    fn translate(self, dx: f64, dy: f64) -> Self {
+        match self {
+            Item::Text { x, y, size, text } => Item::Text { x: x + dx, y: y + dy, size, text },
+            Item::Line { x1, y1, x2, y2 } => Item::Line { x1: x1 + dx, y1: y1 + dy, x2: x2 + dx, y2: y2 + dy },
+            Item::Rect { x, y, width, height } => Item::Rect { x: x + dx, y: y + dy, width, height },
+        }
+    }
+
+    fn to_svg(&self) -> String {
+        match self {
+            Item::Text { x, y, size, text } => {
+                format!("<text x=\"{:.2}\" y=\"{:.2}\" font-size=\"{:.2}\">{}</text>", x, y, size, escape(text))
+            },
+            Item::Line { x1, y1, x2, y2 } => {
+                format!("<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"black\"/>", x1, y1, x2, y2)
+            },
+            Item::Rect { x, y, width, height } => {
+                format!("<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" rx=\"4\" fill=\"white\" stroke=\"black\"/>",
+                    x, y, width, height)
+            },
+        }
+    }
+}
+
+// a laid out piece of the drawing whose origin is the left end of its baseline
+#[derive(Debug, Clone, Default)]
+struct Block {
+    width: f64,
+    ascent: f64,
+    descent: f64,
+    items: Vec<Item>,
+}
+
+impl Block {
+    fn text(text: &str, size: f64) -> Self {
+        let width = text
+            .chars()
+            .map(|c| if c == '(' || c == ')' { PAREN_WIDTH } else { CHAR_WIDTH })
+            .sum::<f64>() * size;
+
+        Self {
+            width,
+            ascent: ASCENT * size,
+            descent: DESCENT * size,
+            items: vec![Item::Text { x: 0.0, y: 0.0, size, text: text.to_string() }],
+        }
+    }
+
+    fn append(&mut self, other: Block) {
+        let dx = self.width;
+        self.items.extend(other.items.into_iter().map(|item| item.translate(dx, 0.0)));
+        self.width += other.width;
+        self.ascent = self.ascent.max(other.ascent);
+        self.descent = self.descent.max(other.descent);
+    }
+
+    // surrounds the block with parentheses as tall as its contents
+    fn paren(self, size: f64) -> Self {
+        let paren_size = size.max(self.ascent + self.descent);
+        // centers the glyphs on the contents
+        let baseline = (self.descent - self.ascent) / 2.0 + (ASCENT - DESCENT) / 2.0 * paren_size;
+        let paren = |s: &str| {
+            let mut block = Block::text(s, paren_size);
+            block.items = block.items.into_iter().map(|item| item.translate(0.0, baseline)).collect();
+            block.ascent -= baseline;
+            block.descent += baseline;
+            block
+        };
+
+        let mut ret = paren("(");
+        ret.append(self);
+        ret.append(paren(")"));
+        ret
+    }
+}
+
+/// Renders `expr` as a standalone SVG image without any external tools.
+pub fn to_svg(expr: &Expr, options: &SvgOptions) -> String {
+    let block = match options.layout {
+        SvgLayout::Typeset => {
+            let size = options.font_size;
+            let mut block = Block::default();
+            if options.show_value {
+                if let Some(value) = expr.eval() {
+                    block.append(Block::text(&value.to_string(), size));
+                    block.append(symbol("=", size));
+                }
+            }
+            block.append(typeset(expr, None, size));
+            block
+        },
+        SvgLayout::Tree => tree(expr, options.font_size),
+    };
+
+    let margin = options.font_size / 2.0;
+    let view_width = block.width + 2.0 * margin;
+    let view_height = block.ascent + block.descent + 2.0 * margin;
+    let height = options.width * view_height / view_width;
+
+    let mut ret = format!(
+        concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.2}\" height=\"{:.2}\" viewBox=\"0 0 {:.2} {:.2}\">\n",
+            "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n",
+            "<g font-family=\"{}\" fill=\"black\" stroke-width=\"{:.2}\">\n",
+        ),
+        options.width, height, view_width, view_height,
+        escape(&options.font_family), options.font_size / 24.0);
+    for item in block.items {
+        ret += &item.translate(margin, margin + block.ascent).to_svg();
+        ret += "\n";
+    }
+    ret += "</g>\n</svg>\n";
+
+    ret
+}
+
+fn symbol(s: &str, size: f64) -> Block {
+    let pad = Block { width: size / 4.0, ..Block::default() };
+
+    let mut ret = pad.clone();
+    ret.append(Block::text(s, size));
+    ret.append(pad);
+    ret
+}
+
+fn typeset(expr: &Expr, parent: Option<(Operator, bool)>, size: f64) -> Block {
+    match expr {
+        // a negative operand such as `2-(-3)` would read as `2--3`
+        Expr::Num(num) if *num < 0 => {
+            let block = Block::text(&format!("\u{2212}{}", num.unsigned_abs()), size);
+            if parent.is_some() {
+                block.paren(size)
+            } else {
+                block
+            }
+        },
+        Expr::Num(num) => Block::text(&num.to_string(), size),
+        Expr::BinOp { op: Operator::Div, lhs, rhs, .. } => {
+            let numer = typeset(lhs, None, size);
+            let denom = typeset(rhs, None, size);
+            let (axis, gap) = (AXIS * size, size / 6.0);
+            let width = numer.width.max(denom.width) + size / 4.0;
+
+            let numer_y = -axis - gap - numer.descent;
+            let denom_y = -axis + gap + denom.ascent;
+            let (numer_x, denom_x) = ((width - numer.width) / 2.0, (width - denom.width) / 2.0);
+            let mut items = vec![Item::Line { x1: 0.0, y1: -axis, x2: width, y2: -axis }];
+            items.extend(numer.items.into_iter().map(|item| item.translate(numer_x, numer_y)));
+            items.extend(denom.items.into_iter().map(|item| item.translate(denom_x, denom_y)));
+
+            Block {
+                width,
+                ascent: numer.ascent - numer_y,
+                descent: denom_y + denom.descent,
+                items,
+            }
+        },
+        Expr::BinOp { op, lhs, rhs, .. } => {
+            let s = match op {
+                Operator::Add => "+",
+                Operator::Sub => "\u{2212}",
+                _ => "\u{D7}",
+            };
+
+            let mut block = typeset(lhs, Some((*op, false)), size);
+            block.append(symbol(s, size));
+            block.append(typeset(rhs, Some((*op, true)), size));
+            if needs_paren(*op, parent) {
+                block.paren(size)
+            } else {
+                block
+            }
+        },
+    }
+}
+
+// lays out the nodes on a grid: leaves take a column each and parents are centered over their children
+fn tree(expr: &Expr, size: f64) -> Block {
+    let mut nodes = Vec::new();
+    let mut column = 0;
+    place(expr, 0, &mut column, &mut nodes);
+
+    let labels: Vec<_> = nodes.iter().map(|node| node_labels(node.expr)).collect();
+    let label_width = |(label, value): &(String, Option<String>)| {
+        let value_width = value.as_ref().map_or(0.0, |value| Block::text(value, size / 2.0).width);
+        Block::text(label, size).width.max(value_width)
+    };
+    let column_width = labels.iter().map(label_width).fold(0.0, f64::max) + size / 2.0;
+    let node_height = 1.6 * size;
+    let row_height = 2.5 * size;
+    let depth = nodes.iter().map(|node| node.depth).max().unwrap_or(0);
+
+    let center = |node: &Node| ((node.column + 0.5) * column_width, node.depth as f64 * row_height);
+    let mut items = Vec::new();
+    for node in &nodes {
+        let (x, y) = center(node);
+        for child in &node.children {
+            let (cx, cy) = center(&nodes[*child]);
+            items.push(Item::Line { x1: x, y1: y + node_height, x2: cx, y2: cy });
+        }
+    }
+
+    for (node, labels) in nodes.iter().zip(&labels) {
+        let (x, y) = center(node);
+        let width = label_width(labels) + size / 4.0;
+        items.push(Item::Rect { x: x - width / 2.0, y, width, height: node_height });
+        let (label, value) = labels;
+        let text_y = if value.is_some() { y + 0.9 * size } else { y + 1.1 * size };
+        items.push(Item::Text {
+            x: x - Block::text(label, size).width / 2.0,
+            y: text_y,
+            size,
+            text: label.clone(),
+        });
+        if let Some(value) = value {
+            items.push(Item::Text {
+                x: x - Block::text(value, size / 2.0).width / 2.0,
+                y: y + 1.4 * size,
+                size: size / 2.0,
+                text: value.clone(),
+            });
+        }
+    }
+
+    Block {
+        width: column as f64 * column_width,
+        ascent: 0.0,
+        descent: depth as f64 * row_height + node_height,
+        items,
+    }
+}
+
+struct Node<'a> {
+    expr: &'a Expr,
+    depth: usize,
+    column: f64,
+    children: Vec<usize>,
+}
+
+// pushes the subtree in post-order and returns the index of its root
+fn place<'a>(expr: &'a Expr, depth: usize, column: &mut usize, nodes: &mut Vec<Node<'a>>) -> usize {
+    let node = match expr {
+        Expr::Num(_) => {
+            *column += 1;
+            Node { expr, depth, column: (*column - 1) as f64, children: Vec::new() }
+        },
+        Expr::BinOp { lhs, rhs, .. } => {
+            let lhs = place(lhs, depth + 1, column, nodes);
+            let rhs = place(rhs, depth + 1, column, nodes);
+            let column = (nodes[lhs].column + nodes[rhs].column) / 2.0;
+            Node { expr, depth, column, children: vec![lhs, rhs] }
+        },
+    };
+
+    nodes.push(node);
+    nodes.len() - 1
+}
+
+fn node_labels(expr: &Expr) -> (String, Option<String>) {
+    match expr {
+        Expr::Num(num) => (num.to_string(), None),
+        Expr::BinOp { op, .. } => {
+            let value = match expr.eval() {
+                Some(value) => value.to_string(),
+                None => "undefined".to_string(),
+            };
+            (op.to_string(), Some(value))
+        },
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::sample_expr;
+
+    fn count(svg: &str, tag: &str) -> usize {
+        svg.matches(&format!("<{} ", tag)).count()
+    }
+
+    #[test]
+    fn typeset() {
+        let svg = to_svg(&sample_expr(), &SvgOptions::default());
+
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"1200.00\" "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert!(svg.contains(">-1237/7</text>"));
+        assert!(svg.contains(">\u{2212}</text>"));
+        // a fraction bar for 22/7
+        assert_eq!(count(&svg, "line"), 1);
+        // -1237/7, =, 22, 7, ×, (, 2, +, 2, ×, 7, ), −, 227
+        assert_eq!(count(&svg, "text"), 14);
+
+        let options = SvgOptions { show_value: false, ..SvgOptions::default() };
+        assert_eq!(count(&to_svg(&sample_expr(), &options), "text"), 12);
+
+        let svg = to_svg(&Expr::binop(Operator::Sub, Expr::Num(2), Expr::Num(-3), false), &options);
+        assert!(svg.contains(">\u{2212}3</text>"));
+        // 2, −, (, −3, )
+        assert_eq!(count(&svg, "text"), 5);
+    }
+
+    #[test]
+    fn tree() {
+        let options = SvgOptions {
+            layout: SvgLayout::Tree,
+            width: 600.0,
+            font_family: "Noto \"Sans\"".to_string(),
+            ..SvgOptions::default()
+        };
+        let svg = to_svg(&sample_expr(), &options);
+
+        assert!(svg.contains("width=\"600.00\""));
+        assert!(svg.contains("font-family=\"Noto &quot;Sans&quot;\""));
+        // 11 nodes and the background
+        assert_eq!(count(&svg, "rect"), 12);
+        assert_eq!(count(&svg, "line"), 10);
+        assert!(svg.contains(">-1237/7</text>"));
+    }
+
+    #[test]
+    fn block() {
+        let block = Block::text("227", 10.0).paren(10.0);
+
+        assert!((block.width - 25.0).abs() < 1e-9);
+        assert!((block.ascent - 8.0).abs() < 1e-9);
+        assert!((block.descent - 2.0).abs() < 1e-9);
+    }
+}
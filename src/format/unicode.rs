@@ -0,0 +1,88 @@
+use crate::{Expr, Operator};
+use crate::expr::needs_paren;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UnicodeOptions {
+    /// Uses full-width digits, parentheses and plus signs, e.g. `（２＋２×７）`.
+    pub full_width: bool,
+}
+
+/// Renders `expr` as plain text with `×`, `÷` and proper minus signs instead of ASCII operators.
+/// The parentheses are the same as those of the `Display` of `Expr`.
+pub fn to_unicode(expr: &Expr, options: &UnicodeOptions) -> String {
+    let mut ret = String::new();
+    unicode_in(expr, None, options, &mut ret);
+    ret
+}
+
+fn unicode_in(expr: &Expr, parent: Option<(Operator, bool)>, options: &UnicodeOptions, out: &mut String) {
+    match expr {
+        Expr::Num(num) => {
+            let paren = *num < 0 && parent.is_some();
+            if paren {
+                out.push(glyph('(', options));
+            }
+            if *num < 0 {
+                out.push('\u{2212}');
+            }
+            out.extend(num.unsigned_abs().to_string().chars().map(|c| glyph(c, options)));
+            if paren {
+                out.push(glyph(')', options));
+            }
+        },
+        Expr::BinOp { op, lhs, rhs, paren } => {
+            let paren = *paren || needs_paren(*op, parent);
+            if paren {
+                out.push(glyph('(', options));
+            }
+            unicode_in(lhs, Some((*op, false)), options, out);
+            out.push(match op {
+                Operator::Add => glyph('+', options),
+                Operator::Sub => '\u{2212}',
+                Operator::Mul => '\u{D7}',
+                Operator::Div => '\u{F7}',
+            });
+            unicode_in(rhs, Some((*op, true)), options, out);
+            if paren {
+                out.push(glyph(')', options));
+            }
+        },
+    }
+}
+
+// maps an ASCII character to its full-width form if requested
+fn glyph(c: char, options: &UnicodeOptions) -> char {
+    if options.full_width && c.is_ascii_graphic() {
+        // the full-width forms are laid out in the same order as ASCII
+        std::char::from_u32(c as u32 - 0x21 + 0xFF01).unwrap_or(c)
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::sample_expr;
+
+    #[test]
+    fn unicode() {
+        let options = UnicodeOptions::default();
+
+        assert_eq!(to_unicode(&sample_expr(), &options), "((22÷7)×(2+2×7)−227)");
+        assert_eq!(to_unicode(&Expr::Num(-34633), &options), "−34633");
+        assert_eq!(
+            to_unicode(&Expr::binop(Operator::Mul, Expr::Num(2), Expr::Num(-3), false), &options),
+            "2×(−3)"
+        );
+    }
+
+    #[test]
+    fn full_width() {
+        let options = UnicodeOptions { full_width: true };
+
+        assert_eq!(to_unicode(&sample_expr(), &options), "（（２２÷７）×（２＋２×７）−２２７）");
+        assert_eq!(to_unicode(&Expr::Num(-1), &options), "−１");
+        assert_eq!(glyph('=', &options), '＝');
+    }
+}
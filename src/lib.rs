@@ -1,48 +1,258 @@
+mod approx;
+mod cache;
+mod expr;
+mod format;
+mod limits;
 mod operator;
+mod puzzle;
+mod seed;
 
-use crate::operator::{Operator, OPERATORS};
+use crate::cache::{CacheKind, read_cache, write_cache};
+use crate::limits::{ENTRY_BYTES, heap_bytes};
+use crate::operator::OPERATORS;
+use crate::seed::expand_group;
 use itertools::iproduct;
-use num::rational::Rational64;
+use num::{rational::Rational64, Zero};
+use rayon::prelude::*;
 use fxhash::{FxHashMap as HashMap, FxHashSet as HashSet};
 use serde::{Serialize, Deserialize};
+use simple_error::{SimpleError, bail};
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-#[derive(Debug)]
+pub use crate::approx::{Approximation, parse_decimal};
+pub use crate::cache::{CACHE_DIR_ENV, CACHE_FORMAT_VERSION, SearchParams, default_cache_dir};
+pub use crate::expr::{Expr, TraceFormat, TraceStep, render_trace};
+pub use crate::format::{
+    CodeLanguage, JsonExpr, SvgLayout, SvgOptions, UnicodeOptions, parse_rpn, parse_sexpr, to_code, to_dot,
+    to_japanese_reading, to_json_ast, to_latex, to_mathml, to_mathml_equation, to_rpn, to_sexpr, to_svg,
+    to_unicode,
+};
+pub use crate::limits::{DenomCut, DepthStats, Limit, SearchLimits};
+pub use crate::operator::Operator;
+pub use crate::puzzle::{CountdownSearch, CountdownSolution, exact_once_exprs, exact_once_target};
+pub use crate::seed::{SeedOrder, validate_expr};
+
+/// The tables of the values generatable from the seeds, by the number of copies used.
+///
+/// A search can be serialized, e.g. as a checkpoint, and extended to a deeper depth later.
+#[derive(Serialize, Deserialize, Debug)]
 pub struct RationalSearch {
-    denom_cut: i64,
+    num_lists: Vec<Vec<i64>>,
+    seed_order: SeedOrder,
+    denom_cut: DenomCut,
     generatable_nums: Vec<Vec<Rational64>>,
-    known_expr: HashMap<Rational64, String>,
+    // expressions are stored as back-pointers into `nodes` and built only on demand
+    nodes: Vec<ExprNode>,
+    known_ids: HashMap<Rational64, usize>,
+    limits: SearchLimits,
+    stats: Vec<DepthStats>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ExprNode {
+    /// An expression made of a single copy of the seed.
+    Seed(Expr),
+    /// `lhs op rhs`, where `lhs` and `rhs` are indices of other nodes.
+    BinOp { op: Operator, lhs: usize, rhs: usize },
 }
 
 impl RationalSearch {
-    fn from_lists(num_lists: &Vec<Vec<Rational64>>, denom_cut: i64) -> Self {
+    pub fn new(num_lists: &[Vec<i64>], search_depth: usize, denom_cut: impl Into<DenomCut>) -> Self {
+        Self::new_with_order(num_lists, SeedOrder::Strict, search_depth, denom_cut)
+    }
+
+    pub fn new_with_order(
+        num_lists: &[Vec<i64>],
+        seed_order: SeedOrder,
+        search_depth: usize,
+        denom_cut: impl Into<DenomCut>,
+    ) -> Self {
+        Self::new_with_limits(num_lists, seed_order, search_depth, denom_cut, SearchLimits::default())
+    }
+
+    pub fn new_with_limits(
+        num_lists: &[Vec<i64>],
+        seed_order: SeedOrder,
+        search_depth: usize,
+        denom_cut: impl Into<DenomCut>,
+        limits: SearchLimits,
+    ) -> Self {
+        // convert value type from i64 into Rational64
+        let rat_num_lists = num_lists
+            .iter()
+            .map(|num_list| {
+                num_list.iter()
+                    .map(|&n| n.into())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut rat_search = Self::from_lists(&rat_num_lists, seed_order, denom_cut);
+        rat_search.limits = limits;
+        rat_search.extend(search_depth);
+
+        rat_search
+    }
+
+    fn from_lists(num_lists: &[Vec<Rational64>], seed_order: SeedOrder, denom_cut: impl Into<DenomCut>) -> Self {
         let mut exprs = HashMap::default();
         for num_list in num_lists {
-            match num_list.len() {
-                1 => {
-                    exprs.insert(
-                        num_list[0],
-                        num_list[0].to_string()
-                    );
-                },
-                2 => { exprs.extend(generate_pair_expr(num_list[0], num_list[1])); },
-                3 => { exprs.extend(generate_triple_expr(num_list[0], num_list[1], num_list[2])); },
-                _ => { unimplemented!(); }
+            for (i, perm) in expand_group(num_list, seed_order).into_iter().enumerate() {
+                let perm_exprs: HashMap<_, _> = match perm.len() {
+                    1 => {
+                        let mut perm_exprs = HashMap::default();
+                        perm_exprs.insert(
+                            perm[0],
+                            leaf(perm[0])
+                        );
+                        perm_exprs
+                    },
+                    // the last expression of each value is the seed
+                    2 => generate_pair_expr(perm[0], perm[1])
+                        .into_iter()
+                        .filter_map(|(num, mut exprs)| Some((num, exprs.pop()?.with_paren())))
+                        .collect(),
+                    3 => generate_triple_expr(perm[0], perm[1], perm[2])
+                        .into_iter()
+                        .filter_map(|(num, mut exprs)| Some((num, exprs.pop()?.with_paren())))
+                        .collect(),
+                    _ => { unimplemented!(); }
+                };
+
+                // the given order takes priority over its permutations
+                if i == 0 {
+                    exprs.extend(perm_exprs);
+                } else {
+                    for (num, expr) in perm_exprs {
+                        exprs.entry(num).or_insert(expr);
+                    }
+                }
             }
         }
 
-        let first_generatable = exprs
-            .iter()
-            .map(|(num, _)| *num)
-            .collect();
+        let mut first_generatable = Vec::with_capacity(exprs.len());
+        let mut nodes = Vec::with_capacity(exprs.len());
+        let mut known_ids = HashMap::default();
+        for (num, expr) in exprs {
+            known_ids.insert(num, nodes.len());
+            nodes.push(ExprNode::Seed(expr));
+            first_generatable.push(num);
+        }
+
+        let stats = vec![DepthStats { entries: first_generatable.len(), ..DepthStats::default() }];
 
         Self {
-            denom_cut,
+            num_lists: num_lists
+                .iter()
+                .map(|num_list| num_list.iter().map(|n| n.to_integer()).collect())
+                .collect(),
+            seed_order,
+            denom_cut: denom_cut.into(),
             generatable_nums: vec![first_generatable],
-            known_expr: exprs
+            nodes,
+            known_ids,
+            limits: SearchLimits::default(),
+            stats,
+        }
+    }
+
+    /// The seed lists of the search.
+    pub fn num_lists(&self) -> &[Vec<i64>] {
+        &self.num_lists
+    }
+
+    pub fn seed_order(&self) -> SeedOrder {
+        self.seed_order
+    }
+
+    pub fn denom_cut(&self) -> &DenomCut {
+        &self.denom_cut
+    }
+
+    /// The number of depths searched so far.
+    pub fn depth(&self) -> usize {
+        self.generatable_nums.len()
+    }
+
+    pub fn limits(&self) -> &SearchLimits {
+        &self.limits
+    }
+
+    /// Drops the depths deeper than `depth`, which gives the same tables as searching to `depth` at once.
+    pub fn truncate(&mut self, depth: usize) {
+        let depth = depth.max(1);
+        if depth >= self.depth() {
+            return;
         }
+
+        // the nodes are added in the order of depths
+        let len: usize = self.stats[..depth].iter().map(|stats| stats.entries).sum();
+        self.nodes.truncate(len);
+        self.known_ids.retain(|_, id| *id < len);
+        self.generatable_nums.truncate(depth);
+        self.stats.truncate(depth);
+    }
+
+    pub fn params(&self) -> SearchParams {
+        SearchParams {
+            num_lists: self.num_lists.clone(),
+            seed_order: self.seed_order,
+            search_depth: self.depth(),
+            denom_cut: self.denom_cut.clone(),
+            limits: self.limits,
+        }
+    }
+
+    /// Writes the search to a cache file with a header of its parameters.
+    pub fn save(&self, writer: impl Write) -> Result<(), SimpleError> {
+        write_cache(writer, CacheKind::Search, &self.params(), self)
+    }
+
+    /// Reads a search written by `save`, checking the header.
+    pub fn load(reader: impl Read) -> Result<Self, SimpleError> {
+        read_cache(reader, CacheKind::Search).map(|(_, search)| search)
+    }
+
+    /// Statistics of each depth, including whether it was pruned by the limits.
+    pub fn stats(&self) -> &[DepthStats] {
+        &self.stats
+    }
+
+    /// A rough estimate of the memory used by the tables, and by a generator built from them, in bytes.
+    pub fn estimated_memory(&self) -> usize {
+        let seeds: usize = self.nodes
+            .iter()
+            .map(|node| match node {
+                ExprNode::Seed(expr) => heap_bytes(expr),
+                ExprNode::BinOp { .. } => 0,
+            })
+            .sum();
+        self.nodes.len() * ENTRY_BYTES + seeds
+    }
+
+    /// Builds the expression found for `num`, if any.
+    pub fn expr(&self, num: &Rational64) -> Option<Expr> {
+        self.known_ids.get(num).map(|&id| self.build_expr(id))
+    }
+
+    // panics if `num` is not generatable
+    pub(crate) fn known_expr(&self, num: &Rational64) -> Expr {
+        self.build_expr(self.known_ids[num])
     }
 
-    fn extend(&mut self, n: usize) {
+    fn build_expr(&self, id: usize) -> Expr {
+        build_expr(&self.nodes, id, true)
+    }
+
+    /// Extends the search to depth `n`. The depths already searched are kept as they are,
+    /// so extending a reloaded search gives the same tables as searching to `n` at once.
+    pub fn extend(&mut self, n: usize) {
+        // the products of the previous depths are searched in parallel, and each new value keeps the
+        // candidate which comes first in the sequential order, so the result does not depend on threads.
+        // when the number of new values is capped by the limits, the values which come first are kept
         let len = self.generatable_nums.len();
         if len >= n {
             return;
@@ -50,36 +260,87 @@ impl RationalSearch {
 
         for k in len..n {
             let generatable_nums = &self.generatable_nums;
-            let mut next_generatable = Vec::new();
-            let loop_iter = (0..k).zip((0..k).rev())
-                .flat_map(move |(i, j)| {
-                    iproduct!(&generatable_nums[i], &generatable_nums[j])
-                });
-
-            for (lval, rval) in loop_iter {
-                for op in &OPERATORS {
-                    if (op == &Operator::Add || op == &Operator::Mul) &&
-                            lval > rval {
-                        continue;
+            let known_ids = &self.known_ids;
+            // the new values are of depth k + 1
+            let denom_cut = self.denom_cut.at(k + 1);
+            let limits = self.limits;
+            let cap = limits.entry_cap(self.estimated_memory());
+            let truncated = AtomicBool::new(false);
+            // partial results are shrunk to the cap, which never drops a value that would be kept
+            let shrink = |found: &mut HashMap<Rational64, Candidate>| {
+                if let Some((cap, _)) = cap {
+                    if found.len() > 2 * cap.max(1) {
+                        truncated.store(true, Ordering::Relaxed);
+                        keep_first(found, cap);
                     }
+                }
+            };
+            let pairs: Vec<_> = (0..k).zip((0..k).rev()).collect();
 
-                    if let Some(num) = op.invoke(*lval, *rval) {
-                        if num.denom() < &self.denom_cut &&
-                                !self.known_expr.contains_key(&num) {
-                            let lexpr = self.known_expr.get(lval).unwrap();
-                            let rexpr = self.known_expr.get(rval).unwrap();
-                            let expr = if op == &Operator::Mul {
-                                format!("{}{}{}", lexpr, op, rexpr)
-                            } else {
-                                format!("({}{}{})", lexpr, op, rexpr)
-                            };
-
-                            self.known_expr.insert(num, expr);
-                            next_generatable.push(num);
+            let candidates = pairs
+                .par_iter()
+                .enumerate()
+                .flat_map(|(p, &(i, j))| {
+                    generatable_nums[i]
+                        .par_iter()
+                        .enumerate()
+                        .map(move |(a, lval)| (p, a, lval, &generatable_nums[j]))
+                })
+                .fold(|| (HashMap::default(), 0), |(mut found, mut pruned_numer), (p, a, lval, rvals)| {
+                    for ((b, rval), (o, op)) in iproduct!(rvals.iter().enumerate(), OPERATORS.iter().enumerate()) {
+                        if (op == &Operator::Add || op == &Operator::Mul) &&
+                                lval > rval {
+                            continue;
+                        }
+
+                        let num = match op.invoke(*lval, *rval) {
+                            Some(num) => num,
+                            None => {
+                                // apart from division by zero, the value overflows and exceeds any max_numer
+                                if limits.max_numer.is_some() && !rval.is_zero() {
+                                    pruned_numer += 1;
+                                }
+                                continue;
+                            },
+                        };
+                        if num.denom() < &denom_cut &&
+                                !known_ids.contains_key(&num) {
+                            if !limits.allows(&num) {
+                                pruned_numer += 1;
+                                continue;
+                            }
+                            found.entry(num).or_insert(((p, a, b, o), *lval, *rval, *op));
                         }
                     }
+                    shrink(&mut found);
+                    (found, pruned_numer)
+                })
+                .reduce(|| (HashMap::default(), 0), |(found, lpruned), (other, rpruned)| {
+                    let mut found = merge_candidates(found, other);
+                    shrink(&mut found);
+                    (found, lpruned + rpruned)
+                });
+            let (candidates, pruned_numer) = candidates;
+
+            let mut candidates: Vec<_> = candidates.into_iter().collect();
+            candidates.sort_unstable_by_key(|(_, (order, ..))| *order);
+            let mut truncated_by = None;
+            if let Some((cap, limit)) = cap {
+                if candidates.len() > cap || truncated.load(Ordering::Relaxed) {
+                    candidates.truncate(cap);
+                    truncated_by = Some(limit);
                 }
             }
+            self.stats.push(DepthStats { entries: candidates.len(), pruned_numer, truncated_by });
+
+            let mut next_generatable = Vec::with_capacity(candidates.len());
+            for (num, (_, lval, rval, op)) in candidates {
+                let node = ExprNode::BinOp { op, lhs: self.known_ids[&lval], rhs: self.known_ids[&rval] };
+
+                self.known_ids.insert(num, self.nodes.len());
+                self.nodes.push(node);
+                next_generatable.push(num);
+            }
 
             next_generatable.sort_by_key(|num| *num.denom());
             self.generatable_nums.push(next_generatable);
@@ -87,64 +348,166 @@ impl RationalSearch {
     }
 }
 
-fn generate_pair_expr(num1: Rational64, num2: Rational64) -> HashMap<Rational64, String> {
+// builds the expression of `nodes[id]`. `explicit_paren` parenthesizes every operation but `*`,
+// which is the style of the generated expressions
+pub(crate) fn build_expr(nodes: &[ExprNode], id: usize, explicit_paren: bool) -> Expr {
+    match &nodes[id] {
+        ExprNode::Seed(expr) => expr.clone(),
+        ExprNode::BinOp { op, lhs, rhs } => {
+            let paren = explicit_paren && op != &Operator::Mul;
+            Expr::binop(*op, build_expr(nodes, *lhs, explicit_paren), build_expr(nodes, *rhs, explicit_paren), paren)
+        },
+    }
+}
+
+// a candidate expression of a new value: its position in the sequential search order
+// (pair of depths, lhs, rhs and operator), the operands and the operator
+type Candidate = ((usize, usize, usize, usize), Rational64, Rational64, Operator);
+
+// keeps the `cap` candidates which come first in the search order
+fn keep_first(found: &mut HashMap<Rational64, Candidate>, cap: usize) {
+    let mut candidates: Vec<_> = found.drain().collect();
+    if cap < candidates.len() {
+        candidates.select_nth_unstable_by_key(cap, |(_, (order, ..))| *order);
+        candidates.truncate(cap);
+    }
+    found.extend(candidates);
+}
+
+fn merge_candidates(
+    mut found: HashMap<Rational64, Candidate>,
+    other: HashMap<Rational64, Candidate>,
+) -> HashMap<Rational64, Candidate> {
+    for (num, candidate) in other {
+        match found.entry(num) {
+            Entry::Occupied(mut entry) => {
+                if candidate.0 < entry.get().0 {
+                    entry.insert(candidate);
+                }
+            },
+            Entry::Vacant(entry) => {
+                entry.insert(candidate);
+            },
+        }
+    }
+    found
+}
+
+fn leaf(num: Rational64) -> Expr {
+    Expr::Num(num.to_integer())
+}
+
+// every expression of `num1 op num2` in the order of OPERATORS, keyed by value
+pub(crate) fn generate_pair_expr(num1: Rational64, num2: Rational64) -> HashMap<Rational64, Vec<Expr>> {
     OPERATORS
         .iter()
         .filter_map(move |op| {
             op.invoke(num1, num2)
-                .map(|res| {
-                    let expr = format!("({}{}{})", num1, op, num2);
-                    (res, expr)
-                })
+                .map(|res| (res, Expr::binop(*op, leaf(num1), leaf(num2), false)))
+        })
+        .fold(HashMap::default(), |mut exprs, (num, expr)| {
+            push_distinct(exprs.entry(num).or_default(), expr);
+            exprs
         })
-        .collect()
 }
 
-fn generate_triple_expr(num1: Rational64, num2: Rational64, num3: Rational64) -> HashMap<Rational64, String> {
+/// Every distinct expression of `num1 op1 num2 op2 num3` in both groupings, keyed by value.
+///
+/// The expressions of each value are in the order of the operators (`op1` first), and an expression
+/// which prints the same as an earlier one replaces it.
+///
+/// ```
+/// use nananiji_calculator::generate_triple_expr;
+///
+/// let exprs = generate_triple_expr(2.into(), 2.into(), 7.into());
+/// let strs: Vec<_> = exprs[&11.into()].iter().map(|expr| expr.to_string()).collect();
+/// assert_eq!(strs, vec!["2+2+7", "2*2+7"]);
+/// ```
+pub fn generate_triple_expr(num1: Rational64, num2: Rational64, num3: Rational64) -> HashMap<Rational64, Vec<Expr>> {
     // ((num1 op1 num2) op2 num3)
-    let invoke_left = move |op1: Operator, op2: Operator| -> Option<(Rational64, String)> {
+    let invoke_left = move |op1: Operator, op2: Operator| -> Option<(Rational64, Expr)> {
         let tmp = op1.invoke(num1, num2)?;
         op2.invoke(tmp, num3)
             .map(move |num| {
-                let expr = if (op1 == Operator::Mul || op1 == Operator::Div) ||
+                let inner_paren = !((op1 == Operator::Mul || op1 == Operator::Div) ||
                         ((op1 == Operator::Add || op1 == Operator::Sub) &&
-                        (op2 == Operator::Add || op2 == Operator::Sub)) {
-                    format!("({}{}{}{}{})", num1, op1, num2, op2, num3)
-                } else {
-                    format!("(({}{}{}){}{})", num1, op1, num2, op2, num3)
-                };
+                        (op2 == Operator::Add || op2 == Operator::Sub)));
+                let expr = Expr::binop(
+                    op2,
+                    Expr::binop(op1, leaf(num1), leaf(num2), inner_paren),
+                    leaf(num3),
+                    false);
                 (num, expr)
             })
     };
 
     // (num1 op1 (num2 op2 num3))
-    let invoke_right = move |op1: Operator, op2: Operator| -> Option<(Rational64, String)> {
+    let invoke_right = move |op1: Operator, op2: Operator| -> Option<(Rational64, Expr)> {
         let tmp = op2.invoke(num2, num3)?;
         op1.invoke(num1, tmp)
             .map(move |num| {
-                let expr = if op1 == Operator::Add ||
-                        (op1 != Operator::Div && (op2 == Operator::Mul || op2 == Operator::Div)) {
-                    format!("({}{}{}{}{})", num1, op1, num2, op2, num3)
-                } else {
-                    format!("({}{}({}{}{}))", num1, op1, num2, op2, num3)
-                };
+                let inner_paren = !(op1 == Operator::Add ||
+                        (op1 != Operator::Div && (op2 == Operator::Mul || op2 == Operator::Div)));
+                let expr = Expr::binop(
+                    op1,
+                    leaf(num1),
+                    Expr::binop(op2, leaf(num2), leaf(num3), inner_paren),
+                    false);
                 (num, expr)
             })
     };
 
     iproduct!(&OPERATORS, &OPERATORS)
         .fold(HashMap::default(), |mut exprs, (op1, op2)| {
-            if let Some((num, expr)) = invoke_left(*op1, *op2) {
-                exprs.insert(num, expr);
-            }
-
-            if let Some((num, expr)) = invoke_right(*op1, *op2) {
-                exprs.insert(num, expr);
+            for (num, expr) in invoke_left(*op1, *op2).into_iter().chain(invoke_right(*op1, *op2)) {
+                push_distinct(exprs.entry(num).or_default(), expr);
             }
             exprs
         })
 }
 
+// appends `expr`, dropping an earlier expression which prints the same
+fn push_distinct(exprs: &mut Vec<Expr>, expr: Expr) {
+    let text = expr.to_string();
+    exprs.retain(|other| other.to_string() != text);
+    exprs.push(expr);
+}
+
+pub fn nananiji_lists() -> Vec<Vec<i64>> {
+    vec![
+        vec![227],
+        vec![22, 7],
+        vec![2, 2, 7],
+    ]
+}
+
+pub fn hanshin_lists(allow_3_34: bool) -> Vec<Vec<i64>> {
+    let mut num_list = vec![
+        vec![334],
+        vec![33, 4],
+        vec![3, 3, 4]
+    ];
+
+    if allow_3_34 {
+        num_list.push(vec![3, 34]);
+    }
+
+    num_list
+}
+
+pub fn kyojin_lists(allow_2_64: bool) -> Vec<Vec<i64>> {
+    let mut num_list = vec![
+        vec![264],
+        vec![26, 4],
+        vec![2, 6, 4]
+    ];
+
+    if allow_2_64 {
+        num_list.push(vec![2, 64]);
+    }
+
+    num_list
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 enum MulExpr {
@@ -156,29 +519,34 @@ enum MulExpr {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ExpressionGenerator {
     search_ordering: Vec<MulExpr>,
-    known_expr: HashMap<i64, String>,
+    // the nodes of the search which the integers depend on, built into expressions only on demand
+    nodes: Vec<ExprNode>,
+    known_ids: HashMap<i64, usize>,
+    known_copies: HashMap<i64, usize>,
+    params: SearchParams,
 }
 
 impl ExpressionGenerator {
-    pub fn from_lists(num_lists: &Vec<Vec<i64>>, search_depth: usize, denom_cut: i64) -> Self {
-        // convert value type from i64 into Rational64
-        let rat_num_lists = num_lists
-            .iter()
-            .map(|num_list| {
-                num_list.iter()
-                    .map(|&n| n.into())
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
+    pub fn from_lists(num_lists: &[Vec<i64>], search_depth: usize, denom_cut: impl Into<DenomCut>) -> Self {
+        Self::from_lists_with_order(num_lists, SeedOrder::Strict, search_depth, denom_cut)
+    }
 
-        let mut rat_search = RationalSearch::from_lists(&rat_num_lists, denom_cut);
-        rat_search.extend(search_depth);
+    pub fn from_lists_with_order(
+        num_lists: &[Vec<i64>],
+        seed_order: SeedOrder,
+        search_depth: usize,
+        denom_cut: impl Into<DenomCut>,
+    ) -> Self {
+        Self::from_search(&RationalSearch::new_with_order(num_lists, seed_order, search_depth, denom_cut))
+    }
 
+    /// Builds the generator from the tables of `rat_search`, e.g. one built with limits.
+    pub fn from_search(rat_search: &RationalSearch) -> Self {
         let gen_nums: Vec<Vec<_>> = rat_search.generatable_nums
-            .into_iter()
+            .iter()
             .map(|nums| {
                 nums
-                    .into_iter()
+                    .iter()
                     .filter_map(|num| {
                         if num.is_integer() {
                             Some(num.to_integer())
@@ -202,108 +570,400 @@ impl ExpressionGenerator {
         let uni_max = gen_nums[0].iter().max().unwrap();
         shrink_ordering(&mut search_ordering, *uni_max);
 
+        let known_copies = gen_nums
+            .iter()
+            .enumerate()
+            .flat_map(|(i, nums)| nums.iter().map(move |num| (*num, i + 1)))
+            .collect();
+
+        // keep only the nodes reachable from the integers. the operands of a node always come before it,
+        // so the nodes are marked backwards and renumbered forwards
+        let mut used = vec![false; rat_search.nodes.len()];
+        for (num, &id) in &rat_search.known_ids {
+            used[id] |= num.is_integer();
+        }
+        for id in (0..used.len()).rev() {
+            if let (true, ExprNode::BinOp { lhs, rhs, .. }) = (used[id], &rat_search.nodes[id]) {
+                used[*lhs] = true;
+                used[*rhs] = true;
+            }
+        }
+
+        let mut new_ids = vec![usize::MAX; used.len()];
+        let mut nodes = Vec::new();
+        for (id, node) in rat_search.nodes.iter().enumerate().filter(|(id, _)| used[*id]) {
+            new_ids[id] = nodes.len();
+            nodes.push(match *node {
+                ExprNode::Seed(ref expr) => ExprNode::Seed(expr.clone()),
+                ExprNode::BinOp { op, lhs, rhs } => ExprNode::BinOp { op, lhs: new_ids[lhs], rhs: new_ids[rhs] },
+            });
+        }
+
         // convert value type from Rational64 into i64
-        let known_expr: HashMap<_, _> = rat_search.known_expr
-            .into_iter()
-            .filter_map(|(num, expr)| {
-                if num.is_integer() {
-                    Some((num.to_integer(), expr))
-                } else {
-                    None
-                }
-            })
+        let known_ids = rat_search.known_ids
+            .iter()
+            .filter(|(num, _)| num.is_integer())
+            .map(|(num, &id)| (num.to_integer(), new_ids[id]))
             .collect();
 
         Self {
             search_ordering,
-            known_expr
+            nodes,
+            known_ids,
+            known_copies,
+            params: rat_search.params(),
         }
     }
 
-    pub fn new_nananiji(search_depth: usize, denom_cut: i64) -> Self {
-        Self::from_lists(&vec![
-            vec![227],
-            vec![22, 7],
-            vec![2, 2, 7],
-        ], search_depth, denom_cut)
+    /// The denominator cut of the search which built this generator.
+    pub fn denom_cut(&self) -> &DenomCut {
+        &self.params.denom_cut
     }
 
-    pub fn new_hanshin(allow_3_34: bool, search_depth: usize, denom_cut: i64) -> Self {
-        let mut num_list = vec![
-            vec![334],
-            vec![33, 4],
-            vec![3, 3, 4]
-        ];
+    /// The parameters of the search which built this generator.
+    pub fn params(&self) -> &SearchParams {
+        &self.params
+    }
 
-        if allow_3_34 {
-            num_list.push(vec![3, 34]);
+    /// Writes the generator to a cache file with a header of its parameters.
+    pub fn save(&self, writer: impl Write) -> Result<(), SimpleError> {
+        write_cache(writer, CacheKind::Generator, &self.params, self)
+    }
+
+    /// Reads a generator written by `save`, checking the header.
+    /// Use [`SearchParams::check`] on `params()` to check the parameters as well.
+    pub fn load(reader: impl Read) -> Result<Self, SimpleError> {
+        read_cache(reader, CacheKind::Generator).map(|(_, generator)| generator)
+    }
+
+    pub fn new_nananiji(search_depth: usize, denom_cut: impl Into<DenomCut>) -> Self {
+        Self::from_lists(&nananiji_lists(), search_depth, denom_cut)
+    }
+
+    pub fn new_hanshin(allow_3_34: bool, search_depth: usize, denom_cut: impl Into<DenomCut>) -> Self {
+        Self::from_lists(&hanshin_lists(allow_3_34), search_depth, denom_cut)
+    }
+
+    pub fn new_kyojin(allow_2_64: bool, search_depth: usize, denom_cut: impl Into<DenomCut>) -> Self {
+        Self::from_lists(&kyojin_lists(allow_2_64), search_depth, denom_cut)
+    }
+
+    /// Fails if `n` cannot be decomposed, e.g. because the arithmetic overflows.
+    pub fn generate(&self, n: i64) -> Result<String, SimpleError> {
+        self.generate_expr(n).map(|expr| expr.to_string())
+    }
+
+    pub fn generate_expr(&self, n: i64) -> Result<Expr, SimpleError> {
+        self.generate_with_count(n).map(|(expr, _)| expr)
+    }
+
+    /// Generates an expression which uses exactly `copies` copies of the seed.
+    ///
+    /// If the expression found by `generate` uses too many copies, the other decompositions are searched.
+    /// An expression which needs fewer copies is padded with zero. The search only tries the decompositions
+    /// over the pre-calculated table, so an error does not mean that no such expression exists.
+    /// At most [`MAX_COPIES`] copies are supported.
+    pub fn generate_with_copies(&self, n: i64, copies: usize) -> Result<Expr, SimpleError> {
+        if copies > MAX_COPIES {
+            bail!("at most {} copies are supported", MAX_COPIES);
         }
 
-        Self::from_lists(&num_list, search_depth, denom_cut)
+        let (expr, used) = self.generate_with_count(n)?;
+        if let Some(expr) = self.pad(expr, used, copies) {
+            return Ok(expr);
+        }
+
+        // the exact count first, then the counts which can be padded
+        let zero_copies = self.known_copies.get(&0).copied().unwrap_or(copies);
+        let counts = std::iter::once(copies).chain((1..=copies.saturating_sub(zero_copies)).rev());
+        let mut search = CopiesSearch { bounds: self.magnitude_bounds(copies), ..CopiesSearch::default() };
+        for used in counts {
+            if let Some(expr) = self.generate_exact(n, used, &mut search) {
+                if let Some(expr) = self.pad(expr, used, copies) {
+                    return Ok(expr);
+                }
+            }
+        }
+
+        if search.visits > MAX_COPIES_VISITS {
+            bail!("gave up searching an expression of {} with {} copies", n, copies);
+        }
+        bail!("no expression of {} with {} copies was found", n, copies)
     }
 
-    pub fn new_kyojin(allow_2_64: bool, search_depth: usize, denom_cut: i64) -> Self {
-        let mut num_list = vec![
-            vec![264],
-            vec![26, 4],
-            vec![2, 6, 4]
-        ];
+    // pads `expr` of `used` copies to `copies` copies as (expr + 0*unit*...*unit)
+    fn pad(&self, expr: Expr, used: usize, copies: usize) -> Option<Expr> {
+        if used == copies {
+            return Some(expr);
+        }
+
+        let zero_copies = *self.known_copies.get(&0)?;
+        if used + zero_copies > copies {
+            return None;
+        }
+        let unit = self.known_copies
+            .iter()
+            .filter(|(_, &c)| c == 1)
+            .map(|(num, _)| *num)
+            .max()?;
 
-        if allow_2_64 {
-            num_list.push(vec![2, 64]);
+        let mut padding = self.known_expr(0)?;
+        for _ in (used + zero_copies)..copies {
+            padding = Expr::binop(Operator::Mul, padding, self.known_expr(unit)?, false);
         }
 
-        Self::from_lists(&num_list, search_depth, denom_cut)
+        Some(Expr::binop(Operator::Add, expr, padding, true))
     }
 
-    pub fn generate(&self, n: i64) -> String {
-        if let Some(expr) = self.known_expr.get(&n) {
-            return expr.to_owned();
-        } else {
-            for expr in &self.search_ordering {
-                match expr {
-                    &MulExpr::Mul(mul) if n % mul == 0 => {
-                        return format!("{}*{}",
-                            self.generate(n / mul),
-                            self.known_expr.get(&mul).unwrap());
-                    },
-                    &MulExpr::MulAdd(mul, add) if (n - add) % mul == 0 => {
-                        if ((n - add) / mul).abs() == 1 {
-                            return format!("({}+{})",
-                                self.generate(n - add),
-                                self.known_expr.get(&add).unwrap(),
-                            );
-                        } else {
-                            return format!("({}*{}+{})",
-                                self.generate((n - add) / mul),
-                                self.known_expr.get(&mul).unwrap(),
-                                self.known_expr.get(&add).unwrap(),
-                            );
-                        }
-                    },
-                    &MulExpr::MulSub(mul, sub) if (n + sub) % mul == 0 => {
-                        if ((n + sub) / mul).abs() == 1 {
-                            return format!("({}-{})",
-                                self.generate(n + sub),
-                                self.known_expr.get(&sub).unwrap(),
-                            );
-                        } else {
-                            return format!("({}*{}-{})",
-                                self.generate((n + sub) / mul),
-                                self.known_expr.get(&mul).unwrap(),
-                                self.known_expr.get(&sub).unwrap(),
-                            );
-                        }
-                    },
-                    _ => {}
+    // upper bounds of the absolute values of the expressions made of up to 0, 1, ..., `copies` copies.
+    // an expression is `rest*mul+add` or `rest+add`, where `mul` and `add` are in the table
+    fn magnitude_bounds(&self, copies: usize) -> Vec<u64> {
+        let mut table = vec![0; copies + 1];
+        for (num, &c) in &self.known_copies {
+            for max in table.iter_mut().skip(c) {
+                *max = num.unsigned_abs().max(*max);
+            }
+        }
+
+        let mut bounds = table.clone();
+        for c in 1..=copies {
+            bounds[c] = bounds[c].max(bounds[c - 1]);
+            for (mul_copies, add_copies) in iproduct!(0..c, 0..c) {
+                if mul_copies + add_copies == 0 || mul_copies + add_copies >= c {
+                    continue;
                 }
+                let mul = if mul_copies == 0 { 1 } else { table[mul_copies] };
+                let bound = bounds[c - mul_copies - add_copies].saturating_mul(mul).saturating_add(table[add_copies]);
+                bounds[c] = bounds[c].max(bound);
+            }
+        }
+        bounds
+    }
+
+    // the first expression of `n` in the search order which uses exactly `copies` copies
+    fn generate_exact(&self, n: i64, copies: usize, search: &mut CopiesSearch) -> Option<Expr> {
+        if copies == 0 || n.unsigned_abs() > search.bounds[copies] || search.visits > MAX_COPIES_VISITS ||
+                search.failed.contains(&(n, copies)) {
+            return None;
+        }
+        search.visits += 1;
+
+        if self.known_copies.get(&n) == Some(&copies) {
+            return self.known_expr(n);
+        }
+
+        for decomposition in self.decompositions(n) {
+            let step = Step::decomposed(n, decomposition);
+            let (rest, leaves) = step.operands();
+            let leaf_copies: usize = leaves.iter().map(|leaf| self.known_copies[leaf]).sum();
+            if leaf_copies >= copies {
+                continue;
             }
-            unimplemented!()
+
+            let rest_copies = copies - leaf_copies;
+            if let Some(rest) = self.generate_exact(rest.unwrap(), rest_copies, search) {
+                return Some(self.apply(&step, (rest, rest_copies)).0);
+            }
+        }
+
+        search.failed.insert((n, copies));
+        None
+    }
+
+    /// Generates the expressions of `targets` in parallel, in the same order as `targets`,
+    /// with the number of copies each uses. With `copies`, every expression uses exactly that many.
+    /// A failure of a target does not affect the others.
+    pub fn generate_batch(&self, targets: &[i64], copies: Option<usize>) -> Vec<Result<(Expr, usize), SimpleError>> {
+        targets
+            .par_iter()
+            .map(|&n| match copies {
+                Some(copies) => self.generate_with_copies(n, copies).map(|expr| (expr, copies)),
+                None => self.generate_with_count(n),
+            })
+            .collect()
+    }
+
+    /// Returns the decomposition steps taken by `generate` in pre-order.
+    pub fn explain(&self, n: i64) -> Result<Vec<Step>, SimpleError> {
+        let mut steps = Vec::new();
+        self.explain_impl(n, &mut steps)?;
+        Ok(steps)
+    }
+
+    fn explain_impl(&self, n: i64, steps: &mut Vec<Step>) -> Result<(), SimpleError> {
+        let step = self.step(n)?;
+        let (rest, leaves) = step.operands();
+        steps.push(step);
+
+        if let Some(rest) = rest {
+            self.explain_impl(rest, steps)?;
+        }
+        for leaf in leaves {
+            self.explain_impl(leaf, steps)?;
+        }
+        Ok(())
+    }
+
+    /// Same as `generate_expr`, but also returns the number of copies the expression uses.
+    pub fn generate_with_count(&self, n: i64) -> Result<(Expr, usize), SimpleError> {
+        match self.step(n)? {
+            Step::Lookup { expr, copies, .. } => Ok((expr, copies)),
+            step => {
+                let rest = self.generate_with_count(step.operands().0.unwrap())?;
+                Ok(self.apply(&step, rest))
+            },
+        }
+    }
+
+    // the step taken by `generate` at `n`, shared by `explain` and `generate_with_count`
+    fn step(&self, n: i64) -> Result<Step, SimpleError> {
+        if let Some(expr) = self.known_expr(n) {
+            return Ok(Step::Lookup { num: n, expr, copies: self.known_copies[&n] });
+        }
+
+        self.decompositions(n)
+            .next()
+            .map(|decomposition| Step::decomposed(n, decomposition))
+            .ok_or_else(|| SimpleError::new(format!("no decomposition of {} is found", n)))
+    }
+
+    // combines the expression of the rest of `step` with its table lookups
+    fn apply(&self, step: &Step, (rest, rest_copies): (Expr, usize)) -> (Expr, usize) {
+        let known = |num: i64| (self.known_expr(num).unwrap(), self.known_copies[&num]);
+        match *step {
+            Step::Lookup { ref expr, copies, .. } => (expr.clone(), copies),
+            Step::Mul { mul, .. } => {
+                let (mul, mul_copies) = known(mul);
+                (Expr::binop(Operator::Mul, rest, mul, false), rest_copies + mul_copies)
+            },
+            Step::Add { add, .. } => {
+                let (add, add_copies) = known(add);
+                (Expr::binop(Operator::Add, rest, add, true), rest_copies + add_copies)
+            },
+            Step::MulAdd { mul, add, .. } => {
+                let (mul, mul_copies) = known(mul);
+                let (add, add_copies) = known(add);
+                let expr = Expr::binop(
+                    Operator::Add,
+                    Expr::binop(Operator::Mul, rest, mul, false),
+                    add,
+                    true);
+                (expr, rest_copies + mul_copies + add_copies)
+            },
+            Step::Sub { sub, .. } => {
+                let (sub, sub_copies) = known(sub);
+                (Expr::binop(Operator::Sub, rest, sub, true), rest_copies + sub_copies)
+            },
+            Step::MulSub { mul, sub, .. } => {
+                let (mul, mul_copies) = known(mul);
+                let (sub, sub_copies) = known(sub);
+                let expr = Expr::binop(
+                    Operator::Sub,
+                    Expr::binop(Operator::Mul, rest, mul, false),
+                    sub,
+                    true);
+                (expr, rest_copies + mul_copies + sub_copies)
+            },
         }
     }
+
+    fn known_expr(&self, num: i64) -> Option<Expr> {
+        self.known_ids.get(&num).map(|&id| build_expr(&self.nodes, id, true))
+    }
+
+    // the entries of search_ordering which decompose n without overflow, together with the quotients
+    fn decompositions(&self, n: i64) -> impl Iterator<Item=(MulExpr, i64)> + '_ {
+        self.search_ordering
+            .iter()
+            .filter_map(move |expr| {
+                let (num, mul) = match *expr {
+                    MulExpr::Mul(mul) => (n, mul),
+                    MulExpr::MulAdd(mul, add) => (n.checked_sub(add)?, mul),
+                    MulExpr::MulSub(mul, sub) => (n.checked_add(sub)?, mul),
+                };
+                if num.checked_rem(mul)? == 0 {
+                    Some((*expr, num.checked_div(mul)?))
+                } else {
+                    None
+                }
+            })
+    }
+}
+
+/// The largest number of copies `ExpressionGenerator::generate_with_copies` accepts.
+/// The padding is a chain of one node per copy, which the formatters walk recursively.
+pub const MAX_COPIES: usize = 1 << 10;
+
+// the number of states visited by `generate_with_copies` before giving up
+const MAX_COPIES_VISITS: usize = 1 << 16;
+
+// the state of the search of `generate_with_copies`: the bounds of the values by the number of copies
+// and the (number, copies) pairs known to be impossible
+#[derive(Default)]
+struct CopiesSearch {
+    bounds: Vec<u64>,
+    failed: HashSet<(i64, usize)>,
+    visits: usize,
 }
 
-fn all_mul_offset_with_score(gen_nums: &Vec<Vec<i64>>) -> Vec<(f64, MulExpr)> {
+/// A step of the decomposition taken by [`ExpressionGenerator::generate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// `num` is found in the pre-calculated table.
+    Lookup { num: i64, expr: Expr, copies: usize },
+    /// `num = quot*mul`
+    Mul { num: i64, quot: i64, mul: i64 },
+    /// `num = quot*mul+add`
+    MulAdd { num: i64, quot: i64, mul: i64, add: i64 },
+    /// `num = quot*mul-sub`
+    MulSub { num: i64, quot: i64, mul: i64, sub: i64 },
+    /// `num = rest+add`
+    Add { num: i64, rest: i64, add: i64 },
+    /// `num = rest-sub`
+    Sub { num: i64, rest: i64, sub: i64 },
+}
+
+impl Step {
+    fn decomposed(num: i64, (expr, quot): (MulExpr, i64)) -> Self {
+        match expr {
+            MulExpr::Mul(mul) => Step::Mul { num, quot, mul },
+            MulExpr::MulAdd(_, add) if quot == 1 || quot == -1 => Step::Add { num, rest: num - add, add },
+            MulExpr::MulAdd(mul, add) => Step::MulAdd { num, quot, mul, add },
+            MulExpr::MulSub(_, sub) if quot == 1 || quot == -1 => Step::Sub { num, rest: num + sub, sub },
+            MulExpr::MulSub(mul, sub) => Step::MulSub { num, quot, mul, sub },
+        }
+    }
+
+    // the number decomposed further and the numbers looked up in the table
+    fn operands(&self) -> (Option<i64>, Vec<i64>) {
+        match *self {
+            Step::Lookup { .. } => (None, vec![]),
+            Step::Mul { quot, mul, .. } => (Some(quot), vec![mul]),
+            Step::MulAdd { quot, mul, add, .. } => (Some(quot), vec![mul, add]),
+            Step::MulSub { quot, mul, sub, .. } => (Some(quot), vec![mul, sub]),
+            Step::Add { rest, add, .. } => (Some(rest), vec![add]),
+            Step::Sub { rest, sub, .. } => (Some(rest), vec![sub]),
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Step::Lookup { num, expr, copies } => {
+                let unit = if *copies == 1 { "copy" } else { "copies" };
+                write!(f, "{} = {} (table, {} {})", num, expr, copies, unit)
+            },
+            Step::Mul { num, quot, mul } => write!(f, "{} = {}*{}", num, quot, mul),
+            Step::MulAdd { num, quot, mul, add } => write!(f, "{} = {}*{}+{}", num, quot, mul, add),
+            Step::MulSub { num, quot, mul, sub } => write!(f, "{} = {}*{}-{}", num, quot, mul, sub),
+            Step::Add { num, rest, add } => write!(f, "{} = {}+{}", num, rest, add),
+            Step::Sub { num, rest, sub } => write!(f, "{} = {}-{}", num, rest, sub),
+        }
+    }
+}
+
+fn all_mul_offset_with_score(gen_nums: &[Vec<i64>]) -> Vec<(f64, MulExpr)> {
     let mut mul_set = HashSet::default();
 
     let mut ret = Vec::new();
@@ -353,34 +1013,18 @@ fn all_mul_offset_with_score(gen_nums: &Vec<Vec<i64>>) -> Vec<(f64, MulExpr)> {
 fn shrink_ordering(search_ordering: &mut Vec<MulExpr>, div: i64) {
     let mut rem_map = HashMap::default();
     for (idx, expr) in search_ordering.iter().enumerate() {
-        match *expr {
-            MulExpr::Mul(mul) if mul == div => {
-                if !rem_map.contains_key(&0) {
-                    rem_map.insert(0, idx);
-                    if rem_map.len() == div as usize {
-                        break;
-                    }
-                }
-            },
-            MulExpr::MulAdd(mul, ofs) if mul == div => {
-                let rem = ((ofs % mul) + mul) % mul;
-                if !rem_map.contains_key(&rem) {
-                    rem_map.insert(rem, idx);
-                    if rem_map.len() == div as usize {
-                        break;
-                    }
-                }
-            },
-            MulExpr::MulSub(mul, ofs) if mul == div => {
-                let rem = ((-ofs % mul) + mul) % mul;
-                if !rem_map.contains_key(&rem) {
-                    rem_map.insert(rem, idx);
-                    if rem_map.len() == div as usize {
-                        break;
-                    }
-                }
-            },
-            _ => {}
+        let rem = match *expr {
+            MulExpr::Mul(mul) if mul == div => 0,
+            MulExpr::MulAdd(mul, ofs) if mul == div => ((ofs % mul) + mul) % mul,
+            MulExpr::MulSub(mul, ofs) if mul == div => ((-ofs % mul) + mul) % mul,
+            _ => continue,
+        };
+
+        if let Entry::Vacant(entry) = rem_map.entry(rem) {
+            entry.insert(idx);
+            if rem_map.len() == div as usize {
+                break;
+            }
         }
     }
 
@@ -401,15 +1045,21 @@ mod test {
     fn test_generate_pair_expr() {
         let mut ans = HashMap::default();
 
-        ans.insert(37.into(), "(33+4)".to_string());
-        ans.insert(29.into(), "(33-4)".to_string());
-        ans.insert(132.into(), "(33*4)".to_string());
-        ans.insert(Rational64::new(33, 4), "(33/4)".to_string());
+        ans.insert(37.into(), vec!["33+4".to_string()]);
+        ans.insert(29.into(), vec!["33-4".to_string()]);
+        ans.insert(132.into(), vec!["33*4".to_string()]);
+        ans.insert(Rational64::new(33, 4), vec!["33/4".to_string()]);
 
-        assert_eq!(
-            generate_pair_expr(33.into(), 4.into()),
-            ans
-        );
+        let to_strings = |exprs: HashMap<Rational64, Vec<Expr>>| -> HashMap<_, Vec<_>> {
+            exprs
+                .into_iter()
+                .map(|(num, exprs)| (num, exprs.iter().map(|expr| expr.to_string()).collect()))
+                .collect()
+        };
+        assert_eq!(to_strings(generate_pair_expr(33.into(), 4.into())), ans);
+
+        // values reached by several operators keep all of them
+        assert_eq!(to_strings(generate_pair_expr(2.into(), 2.into()))[&4.into()], vec!["2+2", "2*2"]);
     }
 
     #[test]
@@ -427,20 +1077,37 @@ mod test {
 
         let result: HashSet<_> = generate_triple_expr(1.into(), 3.into(), 0.into())
             .keys()
-            .map(|k| *k)
+            .copied()
             .collect();
 
         assert_eq!(result, ans);
     }
 
+    #[test]
+    fn build_expr_keeps_required_parens() {
+        // a product on the rhs of a division was printed as `(22/2*7)` before expressions were trees
+        let nodes = vec![
+            ExprNode::Seed(Expr::Num(22)),
+            ExprNode::Seed(Expr::Num(2)),
+            ExprNode::Seed(Expr::Num(7)),
+            ExprNode::BinOp { op: Operator::Mul, lhs: 1, rhs: 2 },
+            ExprNode::BinOp { op: Operator::Div, lhs: 0, rhs: 3 },
+        ];
+
+        let expr = build_expr(&nodes, 4, true);
+        assert_eq!(expr.to_string(), "(22/(2*7))");
+        assert_eq!(expr.eval(), Some(Rational64::new(11, 7)));
+    }
+
     #[test]
     fn from_lists() {
         let exprs = RationalSearch::from_lists(
-            &vec![
+            &[
                 vec![334.into()],
                 vec![33.into(), 4.into()],
                 vec![1.into(), 3.into(), 0.into()]
             ],
+            SeedOrder::Strict,
             30);
 
         let ans: HashSet<_> = vec![
@@ -461,17 +1128,50 @@ mod test {
 
         let result: HashSet<_> = exprs.generatable_nums[0]
             .iter()
-            .map(|num| *num)
+            .copied()
             .collect();
 
         assert_eq!(result, ans);
-        assert_eq!(result, exprs.known_expr.keys().map(|num| *num).collect());
+        assert_eq!(result, exprs.known_ids.keys().copied().collect());
+    }
+
+    #[test]
+    fn from_lists_within_group() {
+        let strict = RationalSearch::from_lists(
+            &[vec![22.into(), 7.into()]],
+            SeedOrder::Strict,
+            30);
+        let within = RationalSearch::from_lists(
+            &[vec![22.into(), 7.into()]],
+            SeedOrder::WithinGroup,
+            30);
+
+        assert_eq!(strict.expr(&Rational64::new(7, 22)), None);
+        assert_eq!(within.known_expr(&Rational64::new(7, 22)).to_string(), "(7/22)");
+        assert_eq!(within.known_expr(&15.into()).to_string(), "(22-7)");
+        assert_eq!(within.known_expr(&29.into()).to_string(), "(22+7)");
+    }
+
+    #[test]
+    fn generated_exprs_follow_seed_order() {
+        let num_lists = nananiji_lists();
+        for &seed_order in &[SeedOrder::Strict, SeedOrder::WithinGroup, SeedOrder::AcrossExpression] {
+            let generator = ExpressionGenerator::from_lists_with_order(&num_lists, seed_order, 2, 10);
+            for n in -300..=300 {
+                let (expr, copies) = generator.generate_with_count(n).unwrap();
+                assert!(validate_expr(&expr.to_string(), &num_lists, seed_order), "{} ({:?})", expr, seed_order);
+
+                let padded = generator.generate_with_copies(n, copies + 2).unwrap();
+                assert!(validate_expr(&padded.to_string(), &num_lists, seed_order), "{} ({:?})", padded, seed_order);
+            }
+        }
     }
 
     #[test]
     fn extend() {
         let mut exprs = RationalSearch::from_lists(
-            &vec![vec![334.into()]],
+            &[vec![334.into()]],
+            SeedOrder::Strict,
             30);
 
         exprs.extend(2);
@@ -493,6 +1193,224 @@ mod test {
         ]
             .into_iter()
             .collect();
-        assert_eq!(ans_set, exprs.known_expr.keys().map(|num| *num).collect());
+        assert_eq!(ans_set, exprs.known_ids.keys().copied().collect());
+        assert_eq!(exprs.known_expr(&111556.into()).to_string(), "334*334");
+        assert_eq!(exprs.nodes.len(), 5);
+    }
+
+    #[test]
+    fn extend_deterministic() {
+        let search = |threads: usize| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| RationalSearch::new(&nananiji_lists(), 3, 10))
+        };
+        let (single, multi) = (search(1), search(4));
+
+        assert_eq!(single.generatable_nums, multi.generatable_nums);
+        assert_eq!(single.known_ids, multi.known_ids);
+        assert_eq!(single.nodes, multi.nodes);
+    }
+
+    #[test]
+    fn extend_with_limits() {
+        let full = RationalSearch::new(&nananiji_lists(), 3, 10);
+        let limits = SearchLimits { max_entries_per_depth: Some(5), ..SearchLimits::default() };
+        let search = |threads: usize| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| RationalSearch::new_with_limits(&nananiji_lists(), SeedOrder::Strict, 3, 10, limits))
+        };
+        let limited = search(1);
+
+        // the values found first are kept
+        let mut first: Vec<_> = full.generatable_nums[1].clone();
+        first.sort_by_key(|num| full.known_ids[num]);
+        let mut kept = limited.generatable_nums[1].clone();
+        kept.sort_by_key(|num| limited.known_ids[num]);
+        assert_eq!(kept, first[..5]);
+
+        assert_eq!(limited.stats()[0], DepthStats { entries: 23, ..DepthStats::default() });
+        assert_eq!(limited.stats()[1].truncated_by, Some(Limit::Entries));
+        assert_eq!(limited.stats()[2].entries, 5);
+        assert_eq!(limited.generatable_nums, search(4).generatable_nums);
+        assert_eq!(limited.known_ids, search(4).known_ids);
+
+        let limits = SearchLimits { max_numer: Some(100), ..SearchLimits::default() };
+        let limited = RationalSearch::new_with_limits(&nananiji_lists(), SeedOrder::Strict, 2, 10, limits);
+        assert!(limited.generatable_nums[1].iter().all(|num| num.numer().abs() <= 100));
+        assert!(limited.stats()[1].pruned_numer > 0);
+        assert_eq!(limited.stats()[1].truncated_by, None);
+
+        let limits = SearchLimits { memory_budget: Some(full.estimated_memory() / 2), ..SearchLimits::default() };
+        let limited = RationalSearch::new_with_limits(&nananiji_lists(), SeedOrder::Strict, 3, 10, limits);
+        assert!(limited.estimated_memory() <= full.estimated_memory() / 2);
+        assert_eq!(limited.stats()[2].truncated_by, Some(Limit::Memory));
+
+        // overflowing values are pruned by max_numer instead of panicking
+        let huge = vec![vec![i64::MAX / 2], vec![3]];
+        let limits = SearchLimits { max_numer: Some(i64::MAX), ..SearchLimits::default() };
+        let limited = RationalSearch::new_with_limits(&huge, SeedOrder::Strict, 3, 10, limits);
+        assert!(limited.stats()[1].pruned_numer > 0);
+        assert!(limited.known_ids.contains_key(&(i64::MAX / 2 * 2).into()));
+    }
+
+    #[test]
+    fn truncate() {
+        let limits = SearchLimits { max_entries_per_depth: Some(3000), ..SearchLimits::default() };
+        let fresh = RationalSearch::new_with_limits(&nananiji_lists(), SeedOrder::Strict, 2, 10, limits);
+        let mut search = RationalSearch::new_with_limits(&nananiji_lists(), SeedOrder::Strict, 4, 10, limits);
+        search.truncate(2);
+
+        assert_eq!(search.depth(), 2);
+        assert_eq!(search.nodes, fresh.nodes);
+        assert_eq!(search.known_ids, fresh.known_ids);
+        assert_eq!(search.generatable_nums, fresh.generatable_nums);
+        assert_eq!(search.stats(), fresh.stats());
+        assert_eq!(search.params(), fresh.params());
+    }
+
+    #[test]
+    fn extend_with_schedule() {
+        let search = RationalSearch::new(&nananiji_lists(), 3, vec![30, 5]);
+
+        assert!(search.generatable_nums[1].iter().all(|num| *num.denom() < 30));
+        assert!(search.generatable_nums[1].iter().any(|num| *num.denom() >= 10));
+        assert!(search.generatable_nums[2].iter().all(|num| *num.denom() < 5));
+
+        let generator = ExpressionGenerator::from_search(&search);
+        assert_eq!(generator.denom_cut(), &DenomCut::Schedule(vec![30, 5]));
+    }
+
+    #[test]
+    fn extend_reloaded() {
+        let limits = SearchLimits { max_entries_per_depth: Some(3000), ..SearchLimits::default() };
+        let full = RationalSearch::new_with_limits(&hanshin_lists(true), SeedOrder::Strict, 3, vec![30, 5], limits);
+
+        let saved = RationalSearch::new_with_limits(&hanshin_lists(true), SeedOrder::Strict, 2, vec![30, 5], limits);
+        let mut bytes = Vec::new();
+        saved.save(&mut bytes).unwrap();
+        let mut resumed = RationalSearch::load(&bytes[..]).unwrap();
+        assert_eq!(resumed.depth(), 2);
+        assert_eq!(resumed.num_lists(), &hanshin_lists(true)[..]);
+        assert_eq!(resumed.limits(), &limits);
+
+        resumed.extend(3);
+        assert_eq!(resumed.depth(), 3);
+        assert_eq!(resumed.generatable_nums, full.generatable_nums);
+        assert_eq!(resumed.known_ids, full.known_ids);
+        assert_eq!(resumed.nodes, full.nodes);
+        assert_eq!(resumed.stats(), full.stats());
+    }
+
+    #[test]
+    fn from_search() {
+        let search = RationalSearch::new(&nananiji_lists(), 3, 10);
+        let generator = ExpressionGenerator::from_search(&search);
+
+        // the nodes of fractions which no integer depends on are dropped
+        assert!(generator.nodes.len() < search.nodes.len());
+        for &num in generator.known_ids.keys() {
+            assert_eq!(
+                generator.known_expr(num).unwrap().to_string(),
+                search.known_expr(&num.into()).to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let generator = ExpressionGenerator::new_hanshin(true, 2, vec![30, 5]);
+        let mut bytes = Vec::new();
+        generator.save(&mut bytes).unwrap();
+
+        let loaded = ExpressionGenerator::load(&bytes[..]).unwrap();
+        assert_eq!(loaded.params(), &SearchParams {
+            num_lists: hanshin_lists(true),
+            seed_order: SeedOrder::Strict,
+            search_depth: 2,
+            denom_cut: DenomCut::Schedule(vec![30, 5]),
+            limits: SearchLimits::default(),
+        });
+        assert_eq!(loaded.generate(3463).unwrap(), generator.generate(3463).unwrap());
+        assert!(RationalSearch::load(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn generate_with_copies() {
+        let generator = ExpressionGenerator::new_nananiji(2, 10);
+
+        assert_eq!(generator.generate_with_copies(227, 1).unwrap().to_string(), "227");
+        assert_eq!(generator.generate_with_copies(227, 2).unwrap().to_string(), "(227+((2-2)/7))");
+        assert_eq!(generator.generate_with_copies(227, 4).unwrap().to_string(), "(227+((2-2)/7)*227*227)");
+        assert_eq!(
+            generator.generate_with_copies(3463, 4).unwrap(),
+            generator.generate_expr(3463).unwrap()
+        );
+        assert_eq!(generator.generate_with_copies(227, 4).unwrap().eval(), Some(227.into()));
+        assert!(generator.generate_with_copies(227, 0).is_err());
+        assert_eq!(
+            generator.generate_with_copies(3463, 3).unwrap_err().to_string(),
+            "no expression of 3463 with 3 copies was found"
+        );
+
+        let expr = generator.generate_with_copies(227, MAX_COPIES).unwrap();
+        assert!(expr.to_string().ends_with("*227*227)"));
+        assert_eq!(expr.eval(), Some(227.into()));
+        assert!(generator.generate_with_copies(227, MAX_COPIES + 1).is_err());
+
+        // the greedy decomposition of 66 uses 5 copies, but other decompositions use fewer
+        assert_eq!(generator.generate_with_count(66).unwrap().1, 5);
+        let expr = generator.generate_with_copies(66, 3).unwrap();
+        assert_eq!(expr.to_string(), "(((2-2)/7)-(2/2-7))*(2*2+7)");
+        assert_eq!(expr.eval(), Some(66.into()));
+        assert_eq!(generator.generate_with_copies(66, 4).unwrap().eval(), Some(66.into()));
+    }
+
+    #[test]
+    fn generate_batch() {
+        let generator = ExpressionGenerator::new_nananiji(2, 10);
+        let targets: Vec<_> = (-500..500).collect();
+        let expected: Vec<_> = targets.iter().map(|&n| generator.generate_with_count(n)).collect();
+
+        assert_eq!(generator.generate_batch(&targets, None), expected);
+        assert!(generator.generate_batch(&[], None).is_empty());
+
+        let padded: Vec<_> = targets.iter().map(|&n| generator.generate_with_copies(n, 8).map(|expr| (expr, 8))).collect();
+        assert_eq!(generator.generate_batch(&targets, Some(8)), padded);
+
+        // the arithmetic of the decomposition never overflows
+        for result in generator.generate_batch(&[i64::MIN, i64::MAX, i64::MIN + 1], None) {
+            let (expr, _) = result.unwrap();
+            assert!(expr.eval().is_some());
+        }
+    }
+
+    #[test]
+    fn explain() {
+        let generator = ExpressionGenerator::new_nananiji(2, 10);
+
+        assert_eq!(
+            generator.explain(227).unwrap(),
+            vec![Step::Lookup { num: 227, expr: Expr::Num(227), copies: 1 }]
+        );
+
+        let steps = generator.explain(-34633).unwrap();
+        assert_eq!(steps[0], Step::MulAdd { num: -34633, quot: -83, mul: 420, add: 227 });
+        assert_eq!(steps[1], Step::Add { num: -83, rest: -237, add: 154 });
+        assert_eq!(steps.len(), 6);
+        assert_eq!(steps[0].to_string(), "-34633 = -83*420+227");
+        assert_eq!(steps[5].to_string(), "227 = 227 (table, 1 copy)");
+
+        // explain takes the same steps as generate
+        let lookups: usize = generator.explain(i64::MIN).unwrap()
+            .iter()
+            .map(|step| if let Step::Lookup { copies, .. } = step { *copies } else { 0 })
+            .sum();
+        assert_eq!(lookups, generator.generate_with_count(i64::MIN).unwrap().1);
     }
 }
@@ -0,0 +1,219 @@
+use crate::ExprNode;
+use crate::expr::Expr;
+use num::rational::Rational64;
+use serde::{Serialize, Deserialize};
+use simple_error::SimpleError;
+use std::{fmt, mem::size_of, str::FromStr};
+
+// a rough size of a value in the search tables: its node, its entry of the id map and its slot
+// in `generatable_nums`, with the spare capacity of the hash map
+const SEARCH_ENTRY_BYTES: usize =
+    size_of::<ExprNode>() + 2 * size_of::<(Rational64, usize)>() + size_of::<Rational64>();
+// the same of a generator built from the search, if the value is an integer:
+// its node and its entries of the id and copies maps
+const GENERATOR_ENTRY_BYTES: usize = size_of::<ExprNode>() + 2 * 2 * size_of::<(i64, usize)>();
+pub(crate) const ENTRY_BYTES: usize = SEARCH_ENTRY_BYTES + GENERATOR_ENTRY_BYTES;
+
+// the size of the boxed operands behind `expr`
+pub(crate) fn heap_bytes(expr: &Expr) -> usize {
+    match expr {
+        Expr::Num(_) => 0,
+        Expr::BinOp { lhs, rhs, .. } => 2 * size_of::<Expr>() + heap_bytes(lhs) + heap_bytes(rhs),
+    }
+}
+
+/// The cut of denominators at each depth of [`crate::RationalSearch`]:
+/// values whose denominator is not less than the cut are discarded.
+///
+/// Depth 1 consists of the seeds themselves and is never cut.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum DenomCut {
+    /// The same cut at every depth.
+    Fixed(i64),
+    /// The cuts of depth 2, 3, ... in order. The last one also applies to the deeper depths.
+    Schedule(Vec<i64>),
+}
+
+impl DenomCut {
+    /// Builds a schedule up to `search_depth` from a function of the depth.
+    pub fn from_fn(search_depth: usize, f: impl Fn(usize) -> i64) -> Self {
+        DenomCut::Schedule((2..=search_depth.max(2)).map(f).collect())
+    }
+
+    /// The cut at `depth` (1-based).
+    pub fn at(&self, depth: usize) -> i64 {
+        match self {
+            DenomCut::Fixed(cut) => *cut,
+            DenomCut::Schedule(cuts) => {
+                let idx = depth.saturating_sub(2).min(cuts.len().saturating_sub(1));
+                cuts.get(idx).copied().unwrap_or(i64::MAX)
+            },
+        }
+    }
+}
+
+impl From<i64> for DenomCut {
+    fn from(cut: i64) -> Self {
+        DenomCut::Fixed(cut)
+    }
+}
+
+impl From<Vec<i64>> for DenomCut {
+    fn from(cuts: Vec<i64>) -> Self {
+        DenomCut::Schedule(cuts)
+    }
+}
+
+impl fmt::Display for DenomCut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DenomCut::Fixed(cut) => write!(f, "{}", cut),
+            DenomCut::Schedule(cuts) => {
+                let cuts: Vec<_> = cuts.iter().map(|cut| cut.to_string()).collect();
+                write!(f, "{}", cuts.join(","))
+            },
+        }
+    }
+}
+
+/// Parses `10` or a comma-separated schedule such as `30,10,5`.
+impl FromStr for DenomCut {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let cuts = s
+            .split(',')
+            .map(|cut| cut.trim().parse::<i64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| SimpleError::new(format!("invalid denominator cut {:?}: {}", s, err)))?;
+
+        Ok(match cuts[..] {
+            [cut] => DenomCut::Fixed(cut),
+            _ => DenomCut::Schedule(cuts),
+        })
+    }
+}
+
+/// Limits of the tables built by [`crate::RationalSearch`]. `None` means unlimited.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SearchLimits {
+    /// The maximum number of new values at each depth. The values found first are kept.
+    pub max_entries_per_depth: Option<usize>,
+    /// Values whose numerator exceeds this in absolute value are discarded.
+    pub max_numer: Option<i64>,
+    /// The budget of the tables in bytes, estimated from the number of values.
+    pub memory_budget: Option<usize>,
+}
+
+/// Prints the limits which are set, e.g. `max entries 5000, memory budget 209715200 bytes`.
+impl fmt::Display for SearchLimits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut limits = Vec::new();
+        if let Some(max_entries) = self.max_entries_per_depth {
+            limits.push(format!("max entries {}", max_entries));
+        }
+        if let Some(max_numer) = self.max_numer {
+            limits.push(format!("max numerator {}", max_numer));
+        }
+        if let Some(memory_budget) = self.memory_budget {
+            limits.push(format!("memory budget {} bytes", memory_budget));
+        }
+
+        if limits.is_empty() {
+            write!(f, "no limits")
+        } else {
+            write!(f, "{}", limits.join(", "))
+        }
+    }
+}
+
+/// The limit that truncated a depth.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Entries,
+    Memory,
+}
+
+/// Statistics of a depth of [`crate::RationalSearch`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct DepthStats {
+    /// The number of values newly found at this depth.
+    pub entries: usize,
+    /// The number of candidate expressions discarded by `max_numer`.
+    pub pruned_numer: usize,
+    /// Set if some values of this depth were discarded to stay within the limits.
+    pub truncated_by: Option<Limit>,
+}
+
+impl SearchLimits {
+    // the number of new values allowed at the next depth given the estimated memory used so far,
+    // and the limit which determines it
+    pub(crate) fn entry_cap(&self, used_bytes: usize) -> Option<(usize, Limit)> {
+        let by_entries = self.max_entries_per_depth.map(|cap| (cap, Limit::Entries));
+        let by_memory = self.memory_budget
+            .map(|budget| (budget.saturating_sub(used_bytes) / ENTRY_BYTES, Limit::Memory));
+
+        match (by_entries, by_memory) {
+            (Some(entries), Some(memory)) => Some(if memory.0 < entries.0 { memory } else { entries }),
+            (cap, None) | (None, cap) => cap,
+        }
+    }
+
+    pub(crate) fn allows(&self, num: &Rational64) -> bool {
+        match self.max_numer {
+            Some(max_numer) => num.numer().checked_abs().is_some_and(|numer| numer <= max_numer),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn denom_cut() {
+        assert_eq!(DenomCut::from(10).at(5), 10);
+
+        let schedule: DenomCut = "30,10,5".parse().unwrap();
+        assert_eq!(schedule, DenomCut::Schedule(vec![30, 10, 5]));
+        assert_eq!((schedule.at(2), schedule.at(3), schedule.at(4), schedule.at(6)), (30, 10, 5, 5));
+        assert_eq!(schedule.to_string(), "30,10,5");
+        assert_eq!("10".parse::<DenomCut>().unwrap(), DenomCut::Fixed(10));
+        assert!("10,".parse::<DenomCut>().is_err());
+        assert_eq!(DenomCut::from_fn(4, |depth| 40 / depth as i64), DenomCut::Schedule(vec![20, 13, 10]));
+    }
+
+    #[test]
+    fn entry_cap() {
+        assert_eq!(SearchLimits::default().entry_cap(100), None);
+
+        let limits = SearchLimits {
+            max_entries_per_depth: Some(10),
+            memory_budget: Some(100 * ENTRY_BYTES),
+            ..SearchLimits::default()
+        };
+        assert_eq!(limits.entry_cap(50 * ENTRY_BYTES), Some((10, Limit::Entries)));
+        assert_eq!(limits.entry_cap(95 * ENTRY_BYTES), Some((5, Limit::Memory)));
+        assert_eq!(limits.entry_cap(200 * ENTRY_BYTES), Some((0, Limit::Memory)));
+    }
+
+    #[test]
+    fn heap() {
+        assert_eq!(heap_bytes(&Expr::Num(227)), 0);
+
+        let expr = Expr::binop(crate::Operator::Add, Expr::Num(2), Expr::Num(2), false);
+        assert_eq!(heap_bytes(&expr), 2 * size_of::<Expr>());
+        assert_eq!(heap_bytes(&Expr::binop(crate::Operator::Mul, expr, Expr::Num(7), false)), 4 * size_of::<Expr>());
+    }
+
+    #[test]
+    fn allows() {
+        let limits = SearchLimits { max_numer: Some(100), ..SearchLimits::default() };
+
+        assert!(limits.allows(&Rational64::new(-100, 7)));
+        assert!(!limits.allows(&101.into()));
+        assert!(!limits.allows(&i64::MIN.into()));
+        assert!(SearchLimits::default().allows(&i64::MIN.into()));
+    }
+}
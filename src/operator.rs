@@ -1,7 +1,8 @@
 use std::fmt;
-use num::{rational::Rational64, traits::CheckedDiv};
+use num::{rational::Rational64, traits::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub}};
+use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Operator {
     Add,
     Sub,
@@ -17,26 +18,46 @@ pub const OPERATORS: [Operator; 4] = [
 ];
 
 impl Operator {
+    /// Returns `None` on division by zero or overflow.
     #[inline(always)]
     pub fn invoke(&self, x: Rational64, y: Rational64) -> Option<Rational64> {
-        match self {
-            &Operator::Add => Some(x + y),
-            &Operator::Sub => Some(x - y),
-            &Operator::Mul => Some(x * y),
-            &Operator::Div => {
-                x.checked_div(&y)
-            },
+        match *self {
+            Operator::Add => x.checked_add(&y),
+            Operator::Sub => x.checked_sub(&y),
+            Operator::Mul => x.checked_mul(&y),
+            Operator::Div => x.checked_div(&y),
         }
     }
+
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        OPERATORS
+            .iter()
+            .find(|op| op.to_string() == symbol)
+            .copied()
+    }
+
+    #[inline(always)]
+    pub fn precedence(&self) -> u8 {
+        match *self {
+            Operator::Add | Operator::Sub => 1,
+            Operator::Mul | Operator::Div => 2,
+        }
+    }
+
+    // whether `x op (y op' z)` differs from `x op y op' z` for op' of the same precedence
+    #[inline(always)]
+    pub fn is_right_sensitive(&self) -> bool {
+        *self == Operator::Sub || *self == Operator::Div
+    }
 }
 
 impl fmt::Display for Operator {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            &Operator::Add => write!(f, "+"),
-            &Operator::Sub => write!(f, "-"),
-            &Operator::Mul => write!(f, "*"),
-            &Operator::Div => write!(f, "/"),
+        match *self {
+            Operator::Add => write!(f, "+"),
+            Operator::Sub => write!(f, "-"),
+            Operator::Mul => write!(f, "*"),
+            Operator::Div => write!(f, "/"),
         }
     }
 }
@@ -67,6 +88,25 @@ mod test {
             Rational64::new(1, 3),
             Rational64::new(0, 1),
         ), None);
+        assert_eq!(Operator::Mul.invoke(i64::MAX.into(), 2.into()), None);
+        assert_eq!(Operator::Sub.invoke(i64::MIN.into(), 1.into()), None);
+    }
+
+    #[test]
+    fn precedence() {
+        assert!(Operator::Add.precedence() < Operator::Mul.precedence());
+        assert_eq!(Operator::Add.precedence(), Operator::Sub.precedence());
+        assert_eq!(Operator::Mul.precedence(), Operator::Div.precedence());
+        assert!(Operator::Sub.is_right_sensitive());
+        assert!(!Operator::Mul.is_right_sensitive());
+    }
+
+    #[test]
+    fn from_symbol() {
+        for op in &OPERATORS {
+            assert_eq!(Operator::from_symbol(&op.to_string()), Some(*op));
+        }
+        assert_eq!(Operator::from_symbol("%"), None);
     }
 
     #[test]
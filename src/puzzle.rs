@@ -0,0 +1,247 @@
+use crate::{Expr, ExprNode, build_expr, generate_pair_expr, generate_triple_expr};
+use crate::operator::{Operator, OPERATORS};
+use itertools::iproduct;
+use num::{rational::Rational64, Signed};
+use fxhash::FxHashMap as HashMap;
+use simple_error::{SimpleError, bail};
+use std::collections::BTreeMap;
+
+const MAX_COUNTDOWN_NUMS: usize = 16;
+
+// the size of the largest seed group, which generate_triple_expr covers
+const MAX_EXACT_ONCE_NUMS: usize = 3;
+
+/// Enumerates every value reachable by using each of `nums` exactly once (in the given order),
+/// together with all distinct expressions that evaluate to it, sorted by their text.
+///
+/// This is the seed table of a group (see [`generate_triple_expr`]), so at most 3 numbers are accepted.
+///
+/// ```
+/// use nananiji_calculator::exact_once_exprs;
+///
+/// let exprs = exact_once_exprs(&[2, 2, 7]).unwrap();
+/// let strs: Vec<_> = exprs[&11.into()].iter().map(|expr| expr.to_string()).collect();
+/// assert_eq!(strs, vec!["2*2+7", "2+2+7"]);
+/// ```
+pub fn exact_once_exprs(nums: &[i64]) -> Result<BTreeMap<Rational64, Vec<Expr>>, SimpleError> {
+    let nums: Vec<Rational64> = nums.iter().map(|&num| num.into()).collect();
+    let exprs = match nums[..] {
+        [] => HashMap::default(),
+        [num] => {
+            let mut exprs = HashMap::default();
+            exprs.insert(num, vec![Expr::Num(num.to_integer())]);
+            exprs
+        },
+        [num1, num2] => generate_pair_expr(num1, num2),
+        [num1, num2, num3] => generate_triple_expr(num1, num2, num3),
+        _ => bail!("an exact-once puzzle takes at most {} numbers, but {} were given", MAX_EXACT_ONCE_NUMS, nums.len()),
+    };
+
+    Ok(exprs
+        .into_iter()
+        .map(|(num, mut exprs)| {
+            exprs.sort_by_cached_key(|expr| expr.to_string());
+            (num, exprs)
+        })
+        .collect())
+}
+
+/// Returns all distinct expressions that use each of `nums` exactly once and evaluate to `target`.
+/// An empty result means `target` is unreachable.
+pub fn exact_once_target(nums: &[i64], target: Rational64) -> Result<Vec<Expr>, SimpleError> {
+    Ok(exact_once_exprs(nums)?
+        .remove(&target)
+        .unwrap_or_default())
+}
+
+/// Result of [`CountdownSearch::solve`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CountdownSolution {
+    Exact(Vec<Expr>),
+    Closest(Rational64, Vec<Expr>),
+}
+
+/// Countdown-style search: every number may be used at most once and in any order.
+///
+/// The tables are keyed by the bitmask of used numbers instead of the number of used copies,
+/// and the expressions are stored as back-pointers into the nodes as in `RationalSearch`.
+#[derive(Debug)]
+pub struct CountdownSearch {
+    nodes: Vec<ExprNode>,
+    known_ids: Vec<HashMap<Rational64, usize>>,
+}
+
+impl CountdownSearch {
+    /// Fails if there are more than 16 numbers.
+    pub fn new(nums: &[i64]) -> Result<Self, SimpleError> {
+        if nums.len() > MAX_COUNTDOWN_NUMS {
+            bail!("a countdown puzzle takes at most {} numbers, but {} were given", MAX_COUNTDOWN_NUMS, nums.len());
+        }
+
+        let mut nodes = Vec::new();
+        let mut known_ids = vec![HashMap::default(); 1 << nums.len()];
+        for (i, &num) in nums.iter().enumerate() {
+            known_ids[1 << i].insert(num.into(), nodes.len());
+            nodes.push(ExprNode::Seed(Expr::Num(num)));
+        }
+
+        for mask in 1..known_ids.len() {
+            if mask.count_ones() < 2 {
+                continue;
+            }
+
+            let mut found = HashMap::default();
+            // iterate all proper non-empty subsets of mask
+            let mut sub = (mask - 1) & mask;
+            while sub > 0 {
+                let rest = mask ^ sub;
+                for ((lval, &lhs), (rval, &rhs), op) in
+                        iproduct!(&known_ids[sub], &known_ids[rest], &OPERATORS) {
+                    if (op == &Operator::Add || op == &Operator::Mul) && sub > rest {
+                        continue;
+                    }
+
+                    // overflowing values are skipped
+                    if let Some(num) = op.invoke(*lval, *rval) {
+                        found.entry(num).or_insert(ExprNode::BinOp { op: *op, lhs, rhs });
+                    }
+                }
+                sub = (sub - 1) & mask;
+            }
+
+            for (num, node) in found {
+                known_ids[mask].insert(num, nodes.len());
+                nodes.push(node);
+            }
+        }
+
+        Ok(Self {
+            nodes,
+            known_ids,
+        })
+    }
+
+    /// Returns the expressions which evaluate to `target`, or those of the closest reachable
+    /// value if `target` is unreachable. Returns `None` if there are no numbers at all.
+    pub fn solve(&self, target: Rational64) -> Option<CountdownSolution> {
+        let exact = self.exprs_for(target);
+        if !exact.is_empty() {
+            return Some(CountdownSolution::Exact(exact));
+        }
+
+        let closest = self.known_ids
+            .iter()
+            .flat_map(|ids| ids.keys())
+            .min_by_key(|&&num| ((num - target).abs(), num))?;
+
+        Some(CountdownSolution::Closest(*closest, self.exprs_for(*closest)))
+    }
+
+    fn exprs_for(&self, num: Rational64) -> Vec<Expr> {
+        let exprs: BTreeMap<_, _> = self.known_ids
+            .iter()
+            .filter_map(|ids| ids.get(&num))
+            .map(|&id| build_expr(&self.nodes, id, false))
+            .map(|expr| (expr.to_string(), expr))
+            .collect();
+
+        exprs.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn to_strings(exprs: &[Expr]) -> Vec<String> {
+        exprs.iter().map(|expr| expr.to_string()).collect()
+    }
+
+    #[test]
+    fn single() {
+        let mut ans = BTreeMap::new();
+        ans.insert(227.into(), vec![Expr::Num(227)]);
+
+        assert_eq!(exact_once_exprs(&[227]).unwrap(), ans);
+        assert!(exact_once_exprs(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn all_solutions() {
+        let exprs = exact_once_exprs(&[2, 2, 7]).unwrap();
+
+        assert_eq!(exprs.len(), 18);
+        assert_eq!(to_strings(&exprs[&0.into()]), vec!["(2-2)*7", "(2-2)/7"]);
+        assert_eq!(to_strings(&exprs[&Rational64::new(1, 7)]), vec!["2/(2*7)", "2/2/7"]);
+        assert_eq!(to_strings(&exprs[&(-7).into()]), vec!["2-(2+7)", "2-2-7"]);
+        assert_eq!(to_strings(&exprs[&18.into()]), vec!["2*(2+7)"]);
+        assert!(exprs.iter().all(|(num, exprs)| exprs.iter().all(|expr| expr.eval() == Some(*num))));
+    }
+
+    #[test]
+    fn target() {
+        assert!(exact_once_target(&[2, 2, 7], 10.into()).unwrap().is_empty());
+        assert_eq!(
+            to_strings(&exact_once_target(&[2, 2, 7], 28.into()).unwrap()),
+            vec!["(2+2)*7", "2*2*7"]
+        );
+    }
+
+    #[test]
+    fn negative_and_too_many() {
+        assert_eq!(to_strings(&exact_once_target(&[2, -3], 5.into()).unwrap()), vec!["2-(-3)"]);
+        assert_eq!(
+            exact_once_exprs(&[2, 2, 7, 7]).unwrap_err().to_string(),
+            "an exact-once puzzle takes at most 3 numbers, but 4 were given"
+        );
+    }
+
+    #[test]
+    fn countdown_exact() {
+        let search = CountdownSearch::new(&[7, 2, 2]).unwrap();
+
+        match search.solve(5.into()) {
+            Some(CountdownSolution::Exact(exprs)) => assert_eq!(to_strings(&exprs), vec!["7-2"]),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        match search.solve(Rational64::new(2, 7)) {
+            Some(CountdownSolution::Exact(exprs)) => assert_eq!(to_strings(&exprs), vec!["2/7"]),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        match search.solve(8.into()) {
+            Some(CountdownSolution::Exact(exprs)) => assert!(to_strings(&exprs).contains(&"7+2/2".to_string())),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn countdown_closest() {
+        let search = CountdownSearch::new(&[2, 2, 7]).unwrap();
+
+        match search.solve(1000.into()) {
+            Some(CountdownSolution::Closest(num, exprs)) => {
+                assert_eq!(num, 28.into());
+                assert!(!exprs.is_empty());
+            },
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(CountdownSearch::new(&[]).unwrap().solve(1.into()), None);
+    }
+
+    #[test]
+    fn countdown_invalid() {
+        assert_eq!(
+            CountdownSearch::new(&[1; 17]).unwrap_err().to_string(),
+            "a countdown puzzle takes at most 16 numbers, but 17 were given"
+        );
+
+        // overflowing values are skipped instead of panicking
+        let search = CountdownSearch::new(&[i64::MAX, i64::MAX]).unwrap();
+        match search.solve(1.into()) {
+            Some(CountdownSolution::Exact(exprs)) => {
+                assert_eq!(to_strings(&exprs), vec![format!("{}/{}", i64::MAX, i64::MAX)]);
+            },
+            solution => panic!("unexpected solution: {:?}", solution),
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use itertools::Itertools;
+use serde::{Serialize, Deserialize};
+use std::{fmt::Display, hash::Hash, str::FromStr};
+
+/// Policy on how the numbers of seed groups (e.g. `2, 2, 7`) may be reordered.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeedOrder {
+    /// Every group keeps the given order, e.g. `2, 2, 7` but not `7, 2, 2`.
+    #[default]
+    Strict,
+    /// The numbers may be permuted within each group, e.g. `7, 2, 2`.
+    WithinGroup,
+    /// The digits may be permuted freely across the whole expression, e.g. `72, 2`.
+    ///
+    /// The search rearranges the digits of each group, and the validator only checks
+    /// the digits of the whole expression.
+    AcrossExpression,
+}
+
+// the given order comes first, followed by its other distinct permutations
+pub(crate) fn expand_group<T>(group: &[T], seed_order: SeedOrder) -> Vec<Vec<T>>
+where
+    T: Clone + Eq + Hash + Display + FromStr,
+{
+    match seed_order {
+        SeedOrder::Strict => vec![group.to_vec()],
+        SeedOrder::WithinGroup => {
+            group.iter()
+                .cloned()
+                .permutations(group.len())
+                .unique()
+                .collect()
+        },
+        SeedOrder::AcrossExpression => {
+            let digits: String = group.iter().map(|num| num.to_string()).collect();
+            if group.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+                return expand_group(group, SeedOrder::WithinGroup);
+            }
+
+            // every arrangement of the digits, cut into as many numbers as the group has
+            let arranged = digits
+                .chars()
+                .permutations(digits.len())
+                .unique()
+                .flat_map(|perm| {
+                    (1..perm.len())
+                        .combinations(group.len() - 1)
+                        .filter_map(move |cuts| split_digits(&perm, &cuts))
+                });
+            std::iter::once(group.to_vec())
+                .chain(arranged)
+                .unique()
+                .collect()
+        },
+    }
+}
+
+// cuts `digits` before each of `cuts`, rejecting numbers with leading zeros
+fn split_digits<T: FromStr>(digits: &[char], cuts: &[usize]) -> Option<Vec<T>> {
+    let bounds: Vec<_> = std::iter::once(0)
+        .chain(cuts.iter().copied())
+        .chain(std::iter::once(digits.len()))
+        .collect();
+    bounds
+        .windows(2)
+        .map(|bound| {
+            let num: String = digits[bound[0]..bound[1]].iter().collect();
+            if num.len() > 1 && num.starts_with('0') {
+                None
+            } else {
+                num.parse().ok()
+            }
+        })
+        .collect()
+}
+
+/// Checks that `expr` consists of copies of `num_lists` reordered only as `seed_order` allows.
+pub fn validate_expr(expr: &str, num_lists: &[Vec<i64>], seed_order: SeedOrder) -> bool {
+    let tokens: Vec<_> = expr
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|token| !token.is_empty())
+        .collect();
+    if tokens.is_empty() {
+        return false;
+    }
+
+    if seed_order == SeedOrder::AcrossExpression {
+        let counts = digit_counts(tokens.iter().copied());
+        return num_lists.iter().any(|num_list| {
+            let strs: Vec<_> = num_list.iter().map(|num| num.to_string()).collect();
+            is_multiple_of(&counts, &digit_counts(strs.iter().map(|s| s.as_str())))
+        });
+    }
+
+    // valid[i] is true iff tokens[..i] can be split into groups
+    let mut valid = vec![false; tokens.len() + 1];
+    valid[0] = true;
+    for i in 0..tokens.len() {
+        if !valid[i] {
+            continue;
+        }
+
+        for num_list in num_lists {
+            let end = i + num_list.len();
+            if end <= tokens.len() && matches_group(&tokens[i..end], num_list, seed_order) {
+                valid[end] = true;
+            }
+        }
+    }
+
+    valid[tokens.len()]
+}
+
+// assigns each of `nums` the index of the seed group it belongs to, allowing reordering within groups
+pub(crate) fn split_seeds(nums: &[i64], num_lists: &[Vec<i64>]) -> Option<Vec<usize>> {
+    // prev[i] is the start and the group index of the last group of a split of nums[..i]
+    let mut prev: Vec<Option<(usize, usize)>> = vec![None; nums.len() + 1];
+    for i in 0..nums.len() {
+        if i > 0 && prev[i].is_none() {
+            continue;
+        }
+
+        for (idx, num_list) in num_lists.iter().enumerate() {
+            let end = i + num_list.len();
+            if num_list.is_empty() || end > nums.len() || prev[end].is_some() {
+                continue;
+            }
+
+            let mut group = nums[i..end].to_vec();
+            let mut seeds = num_list.clone();
+            group.sort_unstable();
+            seeds.sort_unstable();
+            if group == seeds {
+                prev[end] = Some((i, idx));
+            }
+        }
+    }
+
+    let mut ret = vec![0; nums.len()];
+    let mut end = nums.len();
+    while end > 0 {
+        let (start, idx) = prev[end]?;
+        ret[start..end].iter_mut().for_each(|group| *group = idx);
+        end = start;
+    }
+
+    if nums.is_empty() { None } else { Some(ret) }
+}
+
+fn matches_group(tokens: &[&str], num_list: &[i64], seed_order: SeedOrder) -> bool {
+    let mut group: Vec<_> = num_list.iter().map(|num| num.to_string()).collect();
+    if seed_order == SeedOrder::Strict {
+        return tokens.iter().zip(&group).all(|(token, num)| token == num);
+    }
+
+    let mut tokens = tokens.to_vec();
+    tokens.sort_unstable();
+    group.sort_unstable();
+    tokens.iter().zip(&group).all(|(token, num)| token == num)
+}
+
+fn digit_counts<'a>(strs: impl Iterator<Item=&'a str>) -> [usize; 10] {
+    let mut counts = [0; 10];
+    for c in strs.flat_map(|s| s.bytes()) {
+        counts[(c - b'0') as usize] += 1;
+    }
+    counts
+}
+
+fn is_multiple_of(counts: &[usize; 10], unit: &[usize; 10]) -> bool {
+    let total: usize = unit.iter().sum();
+    if total == 0 {
+        return false;
+    }
+
+    let times = counts.iter().sum::<usize>() / total;
+    times > 0 && counts.iter().zip(unit).all(|(count, u)| *count == u * times)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_expand_group() {
+        assert_eq!(expand_group(&[2, 2, 7], SeedOrder::Strict), vec![vec![2, 2, 7]]);
+        assert_eq!(
+            expand_group(&[2, 2, 7], SeedOrder::WithinGroup),
+            vec![vec![2, 2, 7], vec![2, 7, 2], vec![7, 2, 2]]
+        );
+        assert_eq!(
+            expand_group(&[22, 7], SeedOrder::AcrossExpression),
+            vec![vec![22, 7], vec![2, 27], vec![2, 72], vec![27, 2], vec![7, 22], vec![72, 2]]
+        );
+        assert_eq!(expand_group(&[10], SeedOrder::AcrossExpression), vec![vec![10]]);
+    }
+
+    #[test]
+    fn test_split_seeds() {
+        let num_lists = crate::nananiji_lists();
+
+        assert_eq!(split_seeds(&[2, 2, 7, 227, 22, 7], &num_lists), Some(vec![2, 2, 2, 0, 1, 1]));
+        assert_eq!(split_seeds(&[7, 22], &num_lists), Some(vec![1, 1]));
+        assert_eq!(split_seeds(&[72, 2], &num_lists), None);
+        assert_eq!(split_seeds(&[], &num_lists), None);
+    }
+
+    #[test]
+    fn test_validate_expr() {
+        let num_lists = crate::nananiji_lists();
+
+        assert!(validate_expr("((2+2*7)*227-((22-7)+(22*7)))", &num_lists, SeedOrder::Strict));
+        assert!(!validate_expr("(7*22)", &num_lists, SeedOrder::Strict));
+        assert!(validate_expr("(7*22)", &num_lists, SeedOrder::WithinGroup));
+        assert!(!validate_expr("(72+2)", &num_lists, SeedOrder::WithinGroup));
+        assert!(validate_expr("(72+2)", &num_lists, SeedOrder::AcrossExpression));
+        assert!(!validate_expr("(72+7)", &num_lists, SeedOrder::AcrossExpression));
+        assert!(!validate_expr("", &num_lists, SeedOrder::AcrossExpression));
+    }
+}